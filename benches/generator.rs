@@ -1,10 +1,10 @@
-use std::{io::sink, num::NonZeroU64, time::Duration};
+use std::{io::sink, num::NonZeroU64, sync::Arc, time::Duration};
 
 use criterion::{
     AxisScale, BenchmarkId, Criterion, PlotConfiguration, Throughput, criterion_group,
     criterion_main,
 };
-use ftzz::{Generator, NumFilesWithRatio};
+use ftzz::{Generator, MemoryBackend, NumFilesWithRatio};
 use io_adapters::WriteExtension;
 use tempfile::tempdir;
 
@@ -218,6 +218,38 @@ fn bytes_generate(c: &mut Criterion) {
     }
 }
 
+fn memory_generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memory_generate");
+
+    for num_bytes in [0, 1_000_000] {
+        let num_files = 100_000;
+        group.throughput(Throughput::Elements(num_files));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_bytes),
+            &num_bytes,
+            |b, &num_bytes| {
+                b.iter_with_large_drop(|| {
+                    let backend = Arc::new(MemoryBackend::new());
+
+                    Generator::builder()
+                        .root_dir("root")
+                        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                            NonZeroU64::new(num_files).unwrap(),
+                        ))
+                        .max_depth(5)
+                        .num_bytes(num_bytes)
+                        .backend(backend.clone())
+                        .build()
+                        .generate(&mut sink().write_adapter())
+                        .unwrap();
+
+                    backend
+                });
+            },
+        );
+    }
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().noise_threshold(0.005).warm_up_time(Duration::from_secs(1));
@@ -229,5 +261,6 @@ criterion_group! {
     simple_generate,
     sparse_generate,
     bytes_generate,
+    memory_generate,
 }
 criterion_main!(benches);
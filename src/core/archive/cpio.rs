@@ -0,0 +1,67 @@
+use std::{io, io::Write};
+
+use crate::core::archive::EntryKind;
+
+const MAGIC: &[u8] = b"070701";
+const HEADER_SIZE: usize = 110;
+const TRAILER: &str = "TRAILER!!!";
+
+/// Writes a newc header for an entry of `size` bytes.
+///
+/// The entry's contents must follow, terminated by [`pad`].
+pub fn write_header(
+    out: &mut impl Write,
+    name: &str,
+    kind: EntryKind,
+    size: u64,
+    inode: u32,
+) -> io::Result<()> {
+    let size = u32::try_from(size).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cpio archives cannot contain files of 4 GiB or more",
+        )
+    })?;
+    let (mode, links) = match kind {
+        EntryKind::Dir => (0o040_755, 2),
+        EntryKind::File => (0o100_644, 1),
+    };
+
+    write_entry(out, name.trim_end_matches('/'), inode, mode, links, size)
+}
+
+/// Pads an entry's `size` bytes of contents to a multiple of four bytes.
+pub fn pad(out: &mut impl Write, size: u64) -> io::Result<()> {
+    let padding = size.next_multiple_of(4) - size;
+    out.write_all(&[0; 3][..usize::try_from(padding).unwrap()])
+}
+
+/// Writes the entry which terminates the archive.
+pub fn finish(writer: &mut impl Write) -> io::Result<()> {
+    write_entry(writer, TRAILER, 0, 0, 1, 0)
+}
+
+fn write_entry(
+    out: &mut impl Write,
+    name: &str,
+    inode: u32,
+    mode: u32,
+    links: u32,
+    size: u32,
+) -> io::Result<()> {
+    let name_size = u32::try_from(name.len() + 1).unwrap_or(u32::MAX);
+    let mut header = [0; HEADER_SIZE];
+
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    // Inode, mode, uid, gid, links, mtime, size, device numbers, name size, and
+    // checksum
+    let fields = [inode, mode, 0, 0, links, 0, size, 0, 0, 0, 0, name_size, 0];
+    for (field, value) in header[MAGIC.len()..].chunks_exact_mut(8).zip(fields) {
+        field.copy_from_slice(format!("{value:08X}").as_bytes());
+    }
+
+    out.write_all(&header)?;
+    out.write_all(name.as_bytes())?;
+    out.write_all(&[0])?;
+    pad(out, (HEADER_SIZE + name.len() + 1) as u64)
+}
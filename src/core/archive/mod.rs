@@ -0,0 +1,385 @@
+use std::{
+    fmt,
+    fs::File,
+    io,
+    io::{BufWriter, Write},
+    path::{Component, Path, PathBuf},
+    sync::{
+        Condvar, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use crate::core::{
+    archive::zip::{ContentsWriter as ZipContentsWriter, ZipEncoder},
+    file_contents::{ContentsSink, FileOutcome},
+};
+
+mod cpio;
+mod tar;
+mod zip;
+
+/// The format of an archive the tree is streamed into.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A POSIX ustar stream, using GNU extensions for long paths and large
+    /// files.
+    #[default]
+    Tar,
+    /// A zip file, using ZIP64 extensions once the archive outgrows the
+    /// classic format.
+    Zip(ZipCompression),
+    /// A cpio stream in the "newc" format used by initramfs images.
+    Cpio,
+}
+
+/// How the contents of zip entries are stored.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ZipCompression {
+    #[default]
+    Stored,
+    Deflated,
+}
+
+/// Where an archive is written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveOutput {
+    File(PathBuf),
+    Stdout,
+}
+
+impl From<PathBuf> for ArchiveOutput {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
+impl From<&Path> for ArchiveOutput {
+    fn from(path: &Path) -> Self {
+        Self::File(path.to_path_buf())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum EntryKind {
+    Dir,
+    File,
+}
+
+/// A sink which streams every generated entry into an archive instead of the
+/// filesystem.
+///
+/// Tasks complete in an arbitrary order, so each one reserves a ticket when it
+/// is queued and waits for every earlier task to finish writing before
+/// streaming its own entries. The archive's contents are thus identical
+/// across runs.
+///
+/// Waiting can't deadlock: tickets are reserved in the order tasks are spawned
+/// and the blocking pool starts tasks in that same order, so every task
+/// holding a thread only ever waits on tasks which already hold one.
+pub struct Archive {
+    output: Mutex<OrderedOutput>,
+    /// Signalled whenever a task hands the archive over to the next ticket.
+    turn: Condvar,
+    format: ArchiveFormat,
+    root_dir: PathBuf,
+    /// The normalized root directory under which every entry is named.
+    prefix: String,
+    tickets: AtomicU64,
+}
+
+struct OrderedOutput {
+    writer: CountingWriter,
+    /// The ticket of the task currently allowed to write.
+    next: u64,
+    /// The number of entries written so far.
+    entries: u64,
+    /// The zip central directory, which can only be written once every entry
+    /// has been placed.
+    central_directory: Vec<u8>,
+}
+
+/// Tracks the offset at which the next entry starts.
+struct CountingWriter {
+    inner: BufWriter<Box<dyn Write + Send>>,
+    offset: u64,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl fmt::Debug for Archive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Archive")
+            .field("format", &self.format)
+            .field("root_dir", &self.root_dir)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Archive {
+    pub fn create(
+        output: &ArchiveOutput,
+        format: ArchiveFormat,
+        root_dir: PathBuf,
+    ) -> io::Result<Self> {
+        let writer: Box<dyn Write + Send> = match output {
+            ArchiveOutput::File(path) => Box::new(File::create(path)?),
+            ArchiveOutput::Stdout => Box::new(io::stdout()),
+        };
+        let mut prefix = String::new();
+        for component in root_dir.components() {
+            if let Component::Normal(name) = component {
+                prefix.push_str(&name.to_string_lossy());
+                prefix.push('/');
+            }
+        }
+
+        let archive = Self {
+            output: Mutex::new(OrderedOutput {
+                writer: CountingWriter {
+                    inner: BufWriter::new(writer),
+                    offset: 0,
+                },
+                next: 0,
+                entries: 0,
+                central_directory: Vec::new(),
+            }),
+            turn: Condvar::new(),
+            format,
+            root_dir,
+            prefix,
+            tickets: AtomicU64::new(0),
+        };
+        if !archive.prefix.is_empty() {
+            // Extractors don't all create missing parents, so the root and each of
+            // its ancestors get an entry of their own
+            let mut root = archive.task(archive.reserve());
+            for (end, _) in archive.prefix.match_indices('/') {
+                root.name.clear();
+                root.name.push_str(&archive.prefix[..=end]);
+                root.empty_entry(EntryKind::Dir)?;
+            }
+        }
+        Ok(archive)
+    }
+
+    /// Reserves the position of the next task's entries in the archive.
+    pub fn reserve(&self) -> u64 {
+        self.tickets.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn task(&self, ticket: u64) -> TaskArchive<'_> {
+        TaskArchive {
+            archive: self,
+            ticket,
+            output: None,
+            name: String::new(),
+            size: None,
+            zip: ZipEncoder::default(),
+        }
+    }
+
+    /// Terminates the archive once every task has written its entries.
+    pub fn finish(self) -> io::Result<()> {
+        let OrderedOutput {
+            mut writer,
+            next: _,
+            entries,
+            central_directory,
+        } = self.output.into_inner().unwrap();
+
+        match self.format {
+            ArchiveFormat::Tar => tar::finish(&mut writer)?,
+            ArchiveFormat::Zip(_) => {
+                let offset = writer.offset;
+                zip::finish(&mut writer, &central_directory, offset, entries)?;
+            }
+            ArchiveFormat::Cpio => cpio::finish(&mut writer)?,
+        }
+        writer.flush()
+    }
+
+    /// Blocks until every task before `ticket` has written its entries.
+    fn wait_for(&self, ticket: u64) -> MutexGuard<'_, OrderedOutput> {
+        // A task which panicked mid-entry already dooms the run, so there's no
+        // point in spreading the panic to the tasks waiting on it
+        let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        while output.next != ticket {
+            output = self
+                .turn
+                .wait(output)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        output
+    }
+
+    fn entry_name(&self, path: &Path, dir: bool, name: &mut String) {
+        name.clone_from(&self.prefix);
+        for component in path.strip_prefix(&self.root_dir).unwrap_or(path) {
+            name.push_str(&component.to_string_lossy());
+            name.push('/');
+        }
+        if !dir {
+            name.pop();
+        }
+    }
+}
+
+/// Streams the entries of a single task into the archive.
+///
+/// The task waits for its turn when writing its first entry and hands the
+/// archive over to the next task once dropped.
+pub struct TaskArchive<'a> {
+    archive: &'a Archive,
+    ticket: u64,
+    output: Option<MutexGuard<'a, OrderedOutput>>,
+    /// The name of the entry being written.
+    name: String,
+    /// The size of the file being written, once its contents are known.
+    size: Option<u64>,
+    zip: ZipEncoder,
+}
+
+impl<'a> TaskArchive<'a> {
+    pub fn dir(&mut self, path: &Path) -> io::Result<()> {
+        self.archive.entry_name(path, true, &mut self.name);
+        self.empty_entry(EntryKind::Dir)
+    }
+
+    /// Writes a file whose contents are produced by `contents`.
+    pub fn file(
+        &mut self,
+        path: &Path,
+        contents: impl FnOnce(FileSink<'_, 'a>) -> io::Result<FileOutcome>,
+    ) -> io::Result<FileOutcome> {
+        self.archive.entry_name(path, false, &mut self.name);
+        self.size = None;
+        let outcome = contents(FileSink(self))?;
+
+        let size = if let Some(size) = self.size {
+            size
+        } else {
+            self.begin(EntryKind::File, 0)?;
+            0
+        };
+        debug_assert_eq!(outcome.bytes, size);
+        self.end(size)?;
+        Ok(outcome)
+    }
+
+    fn empty_entry(&mut self, kind: EntryKind) -> io::Result<()> {
+        self.begin(kind, 0)?;
+        self.end(0)
+    }
+
+    /// Starts an entry of `size` bytes named [`Self::name`], returning where
+    /// its contents go.
+    #[allow(clippy::cast_possible_truncation)]
+    fn begin(&mut self, kind: EntryKind, size: u64) -> io::Result<EntryWriter<'_>> {
+        let Self {
+            archive,
+            ticket,
+            ref mut output,
+            ref name,
+            size: _,
+            ref mut zip,
+        } = *self;
+        let output: &mut OrderedOutput = output.get_or_insert_with(|| archive.wait_for(ticket));
+
+        output.entries += 1;
+        match archive.format {
+            ArchiveFormat::Tar => tar::write_header(&mut output.writer, name, kind, size)?,
+            ArchiveFormat::Zip(compression) => {
+                return Ok(EntryWriter::Zip(zip.begin(
+                    &mut output.writer,
+                    name,
+                    kind,
+                    size,
+                    compression,
+                )?));
+            }
+            // Inode numbers only need to be unique among hard links, so wrapping
+            // around is harmless
+            ArchiveFormat::Cpio => {
+                cpio::write_header(&mut output.writer, name, kind, size, output.entries as u32)?;
+            }
+        }
+        Ok(EntryWriter::Archive(&mut output.writer))
+    }
+
+    /// Finishes the entry started by [`Self::begin`].
+    fn end(&mut self, size: u64) -> io::Result<()> {
+        let Self {
+            archive,
+            ticket,
+            ref mut output,
+            ref name,
+            size: _,
+            ref mut zip,
+        } = *self;
+        let output: &mut OrderedOutput = output.get_or_insert_with(|| archive.wait_for(ticket));
+
+        match archive.format {
+            ArchiveFormat::Tar => tar::pad(&mut output.writer, size),
+            ArchiveFormat::Zip(_) => {
+                zip.end(&mut output.writer, &mut output.central_directory, name)
+            }
+            ArchiveFormat::Cpio => cpio::pad(&mut output.writer, size),
+        }
+    }
+}
+
+impl Drop for TaskArchive<'_> {
+    fn drop(&mut self) {
+        // Hand over the archive even if this task failed so later tasks aren't
+        // left waiting forever
+        let mut output = self
+            .output
+            .take()
+            .unwrap_or_else(|| self.archive.wait_for(self.ticket));
+        output.next += 1;
+        drop(output);
+        self.archive.turn.notify_all();
+    }
+}
+
+/// Receives the contents of the file being written by [`TaskArchive::file`].
+pub struct FileSink<'t, 'a>(&'t mut TaskArchive<'a>);
+
+impl ContentsSink for FileSink<'_, '_> {
+    fn open(&mut self, len: u64) -> io::Result<impl Write> {
+        self.0.size = Some(len);
+        self.0.begin(EntryKind::File, len)
+    }
+}
+
+enum EntryWriter<'a> {
+    Archive(&'a mut CountingWriter),
+    Zip(ZipContentsWriter<'a>),
+}
+
+impl Write for EntryWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Archive(writer) => writer.write(buf),
+            Self::Zip(contents) => contents.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Archive(writer) => writer.flush(),
+            Self::Zip(contents) => contents.flush(),
+        }
+    }
+}
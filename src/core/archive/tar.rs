@@ -0,0 +1,96 @@
+use std::{io, io::Write};
+
+use crate::core::archive::EntryKind;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Writes a ustar header for an entry of `size` bytes, preceded by a GNU long
+/// name entry if the name doesn't fit in the header.
+///
+/// The entry's contents must follow, terminated by [`pad`].
+pub fn write_header(
+    out: &mut impl Write,
+    name: &str,
+    kind: EntryKind,
+    size: u64,
+) -> io::Result<()> {
+    let name = name.as_bytes();
+    let (prefix, name) = if let Some(split) = split_name(name) {
+        split
+    } else {
+        let len = name.len() as u64 + 1;
+        out.write_all(&header(b"././@LongLink", &[], b'L', 0, len))?;
+        out.write_all(name)?;
+        out.write_all(&[0])?;
+        pad(out, len)?;
+
+        (&[][..], &name[..100])
+    };
+
+    let (kind, mode) = match kind {
+        EntryKind::Dir => (b'5', 0o755),
+        EntryKind::File => (b'0', 0o644),
+    };
+    out.write_all(&header(name, prefix, kind, mode, size))
+}
+
+/// Pads an entry's `size` bytes of contents to a whole number of blocks.
+#[allow(clippy::cast_possible_truncation)]
+pub fn pad(out: &mut impl Write, size: u64) -> io::Result<()> {
+    let padding = size.next_multiple_of(BLOCK_SIZE as u64) - size;
+    out.write_all(&[0; BLOCK_SIZE][..padding as usize])
+}
+
+/// Writes the two empty blocks which terminate the archive.
+pub fn finish(writer: &mut impl Write) -> io::Result<()> {
+    writer.write_all(&[0; 2 * BLOCK_SIZE])
+}
+
+/// Splits a name into the header's prefix and name fields if it fits.
+fn split_name(name: &[u8]) -> Option<(&[u8], &[u8])> {
+    if name.len() <= 100 {
+        return Some((&[], name));
+    }
+    // The separator between the prefix and name is implied
+    let trimmed = name.strip_suffix(b"/").unwrap_or(name);
+    trimmed
+        .iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b'/')
+        .map(|(i, _)| (&name[..i], &name[i + 1..]))
+        .find(|&(prefix, name)| prefix.len() <= 155 && name.len() <= 100)
+}
+
+fn header(name: &[u8], prefix: &[u8], kind: u8, mode: u64, size: u64) -> [u8; BLOCK_SIZE] {
+    let mut header = [0; BLOCK_SIZE];
+
+    header[..name.len()].copy_from_slice(name);
+    write_octal(&mut header[100..108], mode);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    if size < 1 << 33 {
+        write_octal(&mut header[124..136], size);
+    } else {
+        // GNU base-256 encoding for sizes beyond 8 GiB
+        header[124] = 0x80;
+        header[128..136].copy_from_slice(&size.to_be_bytes());
+    }
+    write_octal(&mut header[136..148], 0);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix);
+
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&b| u64::from(b)).sum();
+    write_octal(&mut header[148..155], checksum);
+    header
+}
+
+/// Writes a zero-padded, NUL-terminated octal number filling the field.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let value = format!("{value:0digits$o}");
+    field[..digits].copy_from_slice(value.as_bytes());
+    field[digits] = 0;
+}
@@ -0,0 +1,381 @@
+#![allow(clippy::cast_possible_truncation)]
+
+use std::{io, io::Write, mem};
+
+use crc32fast::Hasher;
+use miniz_oxide::deflate::core::{
+    CompressorOxide, TDEFLFlush, TDEFLStatus, compress_to_output, create_comp_flags_from_zip_params,
+};
+
+use crate::core::archive::{CountingWriter, EntryKind, ZipCompression};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP64_END: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR: u32 = 0x0706_4b50;
+const END: u32 = 0x0605_4b50;
+
+const VERSION_NEEDED: u16 = 20;
+const VERSION_NEEDED_ZIP64: u16 = 45;
+/// Created on unix by an implementation supporting ZIP64.
+const VERSION_MADE_BY: u16 = 0x0300 | VERSION_NEEDED_ZIP64;
+/// Names are encoded as UTF-8.
+const FLAGS: u16 = 1 << 11;
+/// The CRC and sizes follow the entry's contents in a data descriptor.
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+/// Midnight on 1980-01-01, the earliest representable timestamp.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 1 << 5 | 1;
+const ZIP64_EXTRA: u16 = 1;
+const DEFLATE_LEVEL: i32 = 6;
+
+/// Streams entries into a zip file, reusing one compressor for all of them.
+#[derive(Default)]
+pub struct ZipEncoder {
+    compressor: Option<Box<CompressorOxide>>,
+    crc: Hasher,
+    entry: Option<ZipEntry>,
+}
+
+/// The information repeated in the central directory for an entry.
+struct ZipEntry {
+    /// The offset of the entry's local header.
+    header: u64,
+    /// The offset at which the entry's contents start.
+    data: u64,
+    kind: EntryKind,
+    flags: u16,
+    method: u16,
+    size: u64,
+    zip64: bool,
+}
+
+impl ZipEncoder {
+    /// Writes the local header of an entry with `size` bytes of contents,
+    /// returning where those contents go.
+    ///
+    /// The contents' CRC and compressed size are only known once they've been
+    /// written, so non-empty entries defer them to a data descriptor.
+    pub fn begin<'a>(
+        &'a mut self,
+        writer: &'a mut CountingWriter,
+        name: &str,
+        kind: EntryKind,
+        size: u64,
+        compression: ZipCompression,
+    ) -> io::Result<ContentsWriter<'a>> {
+        // Empty entries are always stored as deflating them only adds overhead
+        let method = match compression {
+            ZipCompression::Deflated if size > 0 => DEFLATED,
+            _ => STORED,
+        };
+        let flags = if size > 0 {
+            FLAGS | FLAG_DATA_DESCRIPTOR
+        } else {
+            FLAGS
+        };
+        let zip64 = max_compressed_size(size, method) >= u64::from(u32::MAX);
+
+        let mut header = Vec::with_capacity(30 + name.len() + 20);
+        put_u32(&mut header, LOCAL_HEADER);
+        put_u16(
+            &mut header,
+            if zip64 {
+                VERSION_NEEDED_ZIP64
+            } else {
+                VERSION_NEEDED
+            },
+        );
+        put_u16(&mut header, flags);
+        put_u16(&mut header, method);
+        put_u16(&mut header, DOS_TIME);
+        put_u16(&mut header, DOS_DATE);
+        // The CRC and sizes, which are either zero or in the data descriptor
+        put_u32(&mut header, 0);
+        let size_field = if zip64 { u32::MAX } else { 0 };
+        put_u32(&mut header, size_field);
+        put_u32(&mut header, size_field);
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, if zip64 { 20 } else { 0 });
+        header.extend_from_slice(name.as_bytes());
+        if zip64 {
+            put_u16(&mut header, ZIP64_EXTRA);
+            put_u16(&mut header, 16);
+            put_u64(&mut header, 0);
+            put_u64(&mut header, 0);
+        }
+
+        let offset = writer.offset;
+        writer.write_all(&header)?;
+        self.entry = Some(ZipEntry {
+            header: offset,
+            data: writer.offset,
+            kind,
+            flags,
+            method,
+            size,
+            zip64,
+        });
+        self.crc.reset();
+
+        let compressor = if method == DEFLATED {
+            let compressor = self.compressor.get_or_insert_with(|| {
+                Box::new(CompressorOxide::new(create_comp_flags_from_zip_params(
+                    DEFLATE_LEVEL,
+                    0,
+                    0,
+                )))
+            });
+            compressor.reset();
+            Some(&mut **compressor)
+        } else {
+            None
+        };
+        Ok(ContentsWriter {
+            writer,
+            crc: &mut self.crc,
+            compressor,
+        })
+    }
+
+    /// Terminates the entry started by [`Self::begin`], recording it in the
+    /// central directory.
+    pub fn end(
+        &mut self,
+        writer: &mut CountingWriter,
+        central_directory: &mut Vec<u8>,
+        name: &str,
+    ) -> io::Result<()> {
+        let entry = self
+            .entry
+            .take()
+            .expect("Entries must be started before they are ended");
+        if entry.method == DEFLATED
+            && let Some(compressor) = &mut self.compressor
+        {
+            deflate(compressor, writer, &[], TDEFLFlush::Finish)?;
+        }
+        let crc = mem::take(&mut self.crc).finalize();
+        let compressed_size = writer.offset - entry.data;
+        debug_assert!(entry.zip64 || compressed_size < u64::from(u32::MAX));
+
+        if entry.flags & FLAG_DATA_DESCRIPTOR != 0 {
+            let mut descriptor = Vec::with_capacity(24);
+            put_u32(&mut descriptor, DATA_DESCRIPTOR);
+            put_u32(&mut descriptor, crc);
+            if entry.zip64 {
+                put_u64(&mut descriptor, compressed_size);
+                put_u64(&mut descriptor, entry.size);
+            } else {
+                put_u32(&mut descriptor, compressed_size as u32);
+                put_u32(&mut descriptor, entry.size as u32);
+            }
+            writer.write_all(&descriptor)?;
+        }
+
+        push_central_header(central_directory, name, &entry, crc, compressed_size);
+        Ok(())
+    }
+}
+
+/// Receives an entry's contents, compressing them if needed.
+pub struct ContentsWriter<'a> {
+    writer: &'a mut CountingWriter,
+    crc: &'a mut Hasher,
+    compressor: Option<&'a mut CompressorOxide>,
+}
+
+impl Write for ContentsWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.crc.update(buf);
+        match self.compressor {
+            Some(ref mut compressor) => deflate(compressor, self.writer, buf, TDEFLFlush::None)?,
+            None => self.writer.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Feeds the compressor all of `input`, writing out whatever it produces.
+fn deflate(
+    compressor: &mut CompressorOxide,
+    writer: &mut impl Write,
+    mut input: &[u8],
+    flush: TDEFLFlush,
+) -> io::Result<()> {
+    loop {
+        let mut result = Ok(());
+        let (status, consumed) = compress_to_output(compressor, input, flush, |out| {
+            result = writer.write_all(out);
+            result.is_ok()
+        });
+        result?;
+        input = &input[consumed..];
+
+        match status {
+            TDEFLStatus::Done => return Ok(()),
+            TDEFLStatus::Okay if input.is_empty() && flush != TDEFLFlush::Finish => return Ok(()),
+            TDEFLStatus::Okay => {}
+            TDEFLStatus::BadParam | TDEFLStatus::PutBufFailed => {
+                return Err(io::Error::other(format!(
+                    "Failed to compress zip entry: {status:?}"
+                )));
+            }
+        }
+    }
+}
+
+/// An upper bound on the size of an entry's contents once compressed.
+///
+/// Deflate falls back to stored blocks for incompressible data, so it only
+/// ever adds the few bytes of framing each block needs.
+const fn max_compressed_size(size: u64, method: u16) -> u64 {
+    if method == DEFLATED {
+        size + size / 1024 + 64
+    } else {
+        size
+    }
+}
+
+/// Appends an entry's central directory header.
+fn push_central_header(
+    central_directory: &mut Vec<u8>,
+    name: &str,
+    entry: &ZipEntry,
+    crc: u32,
+    compressed_size: u64,
+) {
+    let &ZipEntry {
+        header: offset,
+        data: _,
+        kind,
+        flags,
+        method,
+        size,
+        zip64: _,
+    } = entry;
+    let external_attributes = match kind {
+        EntryKind::Dir => 0o040_755 << 16 | 0x10,
+        EntryKind::File => 0o100_644 << 16,
+    };
+
+    // Only the fields which overflow are stored in the ZIP64 extra field
+    let mut extra = Vec::new();
+    let mut field = |value: u64| {
+        if value >= u64::from(u32::MAX) {
+            put_u64(&mut extra, value);
+            u32::MAX
+        } else {
+            value as u32
+        }
+    };
+    let size = field(size);
+    let compressed_size = field(compressed_size);
+    let offset = field(offset);
+
+    let cd = central_directory;
+    put_u32(cd, CENTRAL_HEADER);
+    put_u16(cd, VERSION_MADE_BY);
+    put_u16(
+        cd,
+        if extra.is_empty() {
+            VERSION_NEEDED
+        } else {
+            VERSION_NEEDED_ZIP64
+        },
+    );
+    put_u16(cd, flags);
+    put_u16(cd, method);
+    put_u16(cd, DOS_TIME);
+    put_u16(cd, DOS_DATE);
+    put_u32(cd, crc);
+    put_u32(cd, compressed_size);
+    put_u32(cd, size);
+    put_u16(cd, name.len() as u16);
+    put_u16(
+        cd,
+        if extra.is_empty() {
+            0
+        } else {
+            4 + extra.len() as u16
+        },
+    );
+    // Comment length, starting disk, and internal attributes
+    put_u16(cd, 0);
+    put_u16(cd, 0);
+    put_u16(cd, 0);
+    put_u32(cd, external_attributes);
+    put_u32(cd, offset);
+    cd.extend_from_slice(name.as_bytes());
+    if !extra.is_empty() {
+        put_u16(cd, ZIP64_EXTRA);
+        put_u16(cd, extra.len() as u16);
+        cd.extend_from_slice(&extra);
+    }
+}
+
+/// Writes the central directory followed by the end of central directory
+/// records, switching to ZIP64 records once the archive outgrows the classic
+/// format.
+pub fn finish(
+    writer: &mut impl Write,
+    central_directory: &[u8],
+    offset: u64,
+    num_entries: u64,
+) -> io::Result<()> {
+    let size = central_directory.len() as u64;
+    let end_offset = offset + size;
+    let zip64 = num_entries >= u64::from(u16::MAX)
+        || size >= u64::from(u32::MAX)
+        || offset >= u64::from(u32::MAX);
+
+    let mut end = Vec::new();
+    if zip64 {
+        put_u32(&mut end, ZIP64_END);
+        put_u64(&mut end, 44);
+        put_u16(&mut end, VERSION_MADE_BY);
+        put_u16(&mut end, VERSION_NEEDED_ZIP64);
+        put_u32(&mut end, 0);
+        put_u32(&mut end, 0);
+        put_u64(&mut end, num_entries);
+        put_u64(&mut end, num_entries);
+        put_u64(&mut end, size);
+        put_u64(&mut end, offset);
+
+        put_u32(&mut end, ZIP64_LOCATOR);
+        put_u32(&mut end, 0);
+        put_u64(&mut end, end_offset);
+        put_u32(&mut end, 1);
+    }
+    put_u32(&mut end, END);
+    put_u16(&mut end, 0);
+    put_u16(&mut end, 0);
+    let num_entries = u16::try_from(num_entries).unwrap_or(u16::MAX);
+    put_u16(&mut end, num_entries);
+    put_u16(&mut end, num_entries);
+    put_u32(&mut end, u32::try_from(size).unwrap_or(u32::MAX));
+    put_u32(&mut end, u32::try_from(offset).unwrap_or(u32::MAX));
+    put_u16(&mut end, 0);
+
+    writer.write_all(central_directory)?;
+    writer.write_all(&end)
+}
+
+fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::{HashMap, hash_map},
+    fmt::Debug,
+    fs,
+    fs::{DirBuilder, File, OpenOptions},
+    io,
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    core::{durability::Syncer, file_contents::FileContents},
+    utils::FastPathBuf,
+};
+
+/// The storage a tree is written to.
+///
+/// Tasks run concurrently, so a backend is shared between every blocking
+/// worker. Paths always start with the generator's root directory.
+///
+/// There is no separate method for changing an entry's metadata: everything
+/// the generator controls is applied as the entry is created, just as `open`
+/// and `mkdir` do, which saves the filesystem a second syscall per entry.
+pub trait Backend: Debug + Send + Sync {
+    /// Creates a directory along with any missing parents, succeeding if it
+    /// already exists.
+    fn create_dir(&self, path: &Path, metadata: &EntryMetadata) -> io::Result<()>;
+
+    /// Creates or truncates a file and fills it with `contents`, which hold
+    /// exactly `metadata.len` bytes.
+    ///
+    /// Parent directories are created concurrently with their files, so this
+    /// must fail with [`io::ErrorKind::NotFound`] before reading any contents
+    /// if the file's parent directory doesn't exist yet. The generator then
+    /// creates the directory and tries again.
+    fn create_file(
+        &self,
+        path: &Path,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+    ) -> io::Result<()>;
+
+    /// Returns the metadata of the regular file at `path` if there is one.
+    ///
+    /// Resumed runs reuse files which already have the expected size.
+    fn file_metadata(&self, path: &Path) -> Option<EntryMetadata>;
+
+    /// Flushes the contents of a created file to durable storage.
+    ///
+    /// Only called if every file must be synced, which backends without
+    /// durable storage can ignore.
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        let _ = path;
+        Ok(())
+    }
+
+    /// Flushes the entries of a directory to durable storage.
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        let _ = path;
+        Ok(())
+    }
+
+    /// Flushes everything written to the storage containing `root_dir` once
+    /// the tree is complete.
+    fn sync_all(&self, root_dir: &Path) -> io::Result<()> {
+        let _ = root_dir;
+        Ok(())
+    }
+
+    /// Creates a file for the generator, which may NUL-terminate `path` in
+    /// place for the syscall, and flushes it if `sync` requires every file to
+    /// be synced.
+    #[doc(hidden)]
+    fn create_file_in_place(
+        &self,
+        path: &mut FastPathBuf,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+        sync: Option<&Syncer>,
+    ) -> io::Result<()> {
+        self.create_file(path, metadata, contents)?;
+        sync.map_or(Ok(()), |sync| sync.file(|| self.sync_file(path)))
+    }
+}
+
+/// The attributes of an entry applied by a [`Backend`] when creating it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EntryMetadata {
+    /// The entry's size in bytes, always zero for directories.
+    pub len: u64,
+    /// The requested permission bits, to which the filesystem applies the
+    /// process's umask.
+    pub mode: u32,
+}
+
+impl EntryMetadata {
+    pub(crate) const DIR: Self = Self {
+        len: 0,
+        mode: 0o777,
+    };
+}
+
+/// Writes the tree to the real filesystem.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Filesystem;
+
+impl Backend for Filesystem {
+    fn create_dir(&self, path: &Path, metadata: &EntryMetadata) -> io::Result<()> {
+        let mut builder = DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, metadata.mode);
+        #[cfg(not(unix))]
+        let _ = metadata;
+        builder.create(path)
+    }
+
+    fn create_file(
+        &self,
+        path: &Path,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+    ) -> io::Result<()> {
+        #[cfg(all(unix, not(miri)))]
+        if metadata.len == 0 {
+            return create_empty_file(path, metadata.mode);
+        }
+        write_file(path, metadata, contents).map(drop)
+    }
+
+    fn file_metadata(&self, path: &Path) -> Option<EntryMetadata> {
+        let metadata = fs::symlink_metadata(path)
+            .ok()
+            .filter(fs::Metadata::is_file)?;
+        #[cfg(unix)]
+        let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777;
+        #[cfg(not(unix))]
+        let mode = crate::core::file_contents::FILE_MODE;
+        Some(EntryMetadata {
+            len: metadata.len(),
+            mode,
+        })
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        // Only reached through backends wrapping the filesystem: the generator
+        // otherwise syncs files through the handle they were written with
+        File::open(path)?.sync_all()
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        // Directories can't be opened as files on other platforms
+        #[cfg(unix)]
+        File::open(path)?.sync_all()?;
+        #[cfg(not(unix))]
+        let _ = path;
+        Ok(())
+    }
+
+    fn sync_all(&self, root_dir: &Path) -> io::Result<()> {
+        sync_filesystem(root_dir)
+    }
+
+    fn create_file_in_place(
+        &self,
+        path: &mut FastPathBuf,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+        sync: Option<&Syncer>,
+    ) -> io::Result<()> {
+        // Syncing through the handle the file was written with saves reopening it
+        let sync = sync.filter(|sync| sync.syncs_files());
+        #[cfg(all(unix, not(miri)))]
+        if metadata.len == 0 && sync.is_none() {
+            return create_empty_file(&*path.to_cstr_mut(), metadata.mode);
+        }
+        let file = write_file(path, metadata, contents)?;
+        sync.map_or(Ok(()), |sync| sync.file(|| file.sync_all()))
+    }
+}
+
+fn write_file(
+    path: &Path,
+    metadata: &EntryMetadata,
+    contents: &mut FileContents<'_>,
+) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, metadata.mode);
+    let mut file = options.open(path)?;
+    io::copy(contents, &mut file)?;
+    Ok(file)
+}
+
+/// Keeps the tree in memory, producing the same layout and contents as the
+/// filesystem without any disk I/O.
+///
+/// As on the filesystem, creating a directory also creates its missing
+/// ancestors and files can only be created in existing directories.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+/// An entry stored by a [`MemoryBackend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    Dir,
+    File {
+        contents: Vec<u8>,
+        /// The requested permission bits.
+        mode: u32,
+    },
+}
+
+impl MemoryBackend {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of every entry created so far.
+    #[must_use]
+    pub fn entries(&self) -> HashMap<PathBuf, Entry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    #[must_use]
+    pub fn into_entries(self) -> HashMap<PathBuf, Entry> {
+        self.entries.into_inner().unwrap()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn create_dir(&self, path: &Path, _: &EntryMetadata) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        for dir in path.ancestors().filter(|dir| !dir.as_os_str().is_empty()) {
+            match entries.entry(dir.to_path_buf()) {
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(Entry::Dir);
+                }
+                // Directories are only ever inserted along with their ancestors
+                hash_map::Entry::Occupied(entry) if *entry.get() == Entry::Dir => break,
+                hash_map::Entry::Occupied(_) => {
+                    return Err(io::Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("{dir:?} is a file"),
+                    ));
+                }
+            }
+        }
+        drop(entries);
+        Ok(())
+    }
+
+    fn create_file(
+        &self,
+        path: &Path,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+    ) -> io::Result<()> {
+        let parent_exists = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .is_none_or(|parent| self.entries.lock().unwrap().get(parent) == Some(&Entry::Dir));
+        if !parent_exists {
+            return Err(ErrorKind::NotFound.into());
+        }
+
+        let mut data = Vec::with_capacity(usize::try_from(metadata.len).unwrap_or(0));
+        contents.read_to_end(&mut data)?;
+        let mut entries = self.entries.lock().unwrap();
+        if entries.get(path) == Some(&Entry::Dir) {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{path:?} is a directory"),
+            ));
+        }
+        entries.insert(path.to_path_buf(), Entry::File {
+            contents: data,
+            mode: metadata.mode,
+        });
+        drop(entries);
+        Ok(())
+    }
+
+    fn file_metadata(&self, path: &Path) -> Option<EntryMetadata> {
+        match self.entries.lock().unwrap().get(path)? {
+            Entry::Dir => None,
+            Entry::File { contents, mode } => Some(EntryMetadata {
+                len: contents.len() as u64,
+                mode: *mode,
+            }),
+        }
+    }
+}
+
+/// Flushes the filesystem containing `root_dir`, or every filesystem where
+/// that isn't possible.
+fn sync_filesystem(root_dir: &Path) -> io::Result<()> {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_os = "linux", not(miri)))] {
+            rustix::fs::syncfs(File::open(root_dir)?).map_err(io::Error::from)
+        } else if #[cfg(all(unix, not(miri)))] {
+            let _ = root_dir;
+            rustix::fs::sync();
+            Ok(())
+        } else {
+            let _ = root_dir;
+            Ok(())
+        }
+    }
+}
+
+/// Creates an empty file without opening it where possible.
+#[cfg(all(unix, not(miri)))]
+fn create_empty_file(path: impl rustix::path::Arg, mode: u32) -> io::Result<()> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            use rustix::fs::{mknodat, FileType, Mode};
+
+            mknodat(
+                rustix::fs::CWD,
+                path,
+                FileType::RegularFile,
+                Mode::from_raw_mode(mode),
+                0,
+            )
+            .map_err(io::Error::from)
+        } else {
+            use rustix::fs::{openat, OFlags, Mode};
+
+            #[allow(clippy::cast_possible_truncation)]
+            openat(
+                rustix::fs::CWD,
+                path,
+                OFlags::CREATE,
+                Mode::from_bits_truncate(mode as _),
+            )
+            .map(drop)
+            .map_err(io::Error::from)
+        }
+    }
+}
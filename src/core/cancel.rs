@@ -0,0 +1,48 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+
+/// A handle which stops a run early when cancelled.
+///
+/// Clones share the same state so the token may be cancelled from another
+/// thread while the generator is running.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the generator to stop queueing new work.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Decides when the scheduler should stop queueing new tasks.
+#[derive(Debug, Default)]
+pub struct StopCondition {
+    pub token: Option<CancellationToken>,
+    pub deadline: Option<Instant>,
+}
+
+impl StopCondition {
+    pub fn should_stop(&self) -> bool {
+        let Self { token, deadline } = self;
+        token.as_ref().is_some_and(CancellationToken::is_cancelled)
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::{Path, PathBuf};
+
+use crate::core::file_contents::ContentHasher;
+
+/// Computes an order-independent digest of a tree.
+///
+/// Each entry is hashed from its path relative to the root directory and, for
+/// files, the hash of its contents. The tree's digest is the wrapping sum of
+/// its entries' hashes so that it doesn't matter in which order tasks
+/// complete.
+#[derive(Debug)]
+pub struct TreeDigest {
+    root_dir: PathBuf,
+}
+
+impl TreeDigest {
+    pub const fn new(root_dir: PathBuf) -> Self {
+        Self { root_dir }
+    }
+
+    pub fn dir(&self, path: &Path) -> u64 {
+        let mut hasher = self.hash_path(path);
+        hasher.update(b"/");
+        hasher.finish()
+    }
+
+    pub fn file(&self, path: &Path, content_hash: u64) -> u64 {
+        let mut hasher = self.hash_path(path);
+        hasher.update(b"\0");
+        hasher.update(&content_hash.to_le_bytes());
+        hasher.finish()
+    }
+
+    fn hash_path(&self, path: &Path) -> ContentHasher<()> {
+        let mut hasher = ContentHasher::new(());
+        // Components are joined manually so the digest is platform independent
+        let relative = path.strip_prefix(&self.root_dir).unwrap_or(path);
+        for (i, component) in relative.components().enumerate() {
+            if i > 0 {
+                hasher.update(b"/");
+            }
+            hasher.update(component.as_os_str().as_encoded_bytes());
+        }
+        hasher
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    io,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::core::backend::Backend;
+
+/// When created entries are flushed to durable storage.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Leave flushing to the operating system.
+    #[default]
+    None,
+    /// Flush each file after writing it.
+    File,
+    /// Flush each directory after creating its files and subdirectories.
+    Dir,
+    /// Flush the whole filesystem once the tree is complete.
+    Filesystem,
+}
+
+/// Applies a [`SyncPolicy`], timing every sync across all tasks.
+#[derive(Debug)]
+pub struct Syncer {
+    policy: SyncPolicy,
+    elapsed_nanos: AtomicU64,
+}
+
+impl Syncer {
+    pub const fn new(policy: SyncPolicy) -> Self {
+        Self {
+            policy,
+            elapsed_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether every file must be synced once it has been written.
+    pub fn syncs_files(&self) -> bool {
+        self.policy == SyncPolicy::File
+    }
+
+    pub fn file(&self, sync: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        if self.syncs_files() {
+            self.timed(sync)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn dir(&self, backend: &dyn Backend, path: &Path) -> io::Result<()> {
+        if self.policy == SyncPolicy::Dir {
+            self.timed(|| backend.sync_dir(path))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn all(&self, backend: &dyn Backend, root_dir: &Path) -> io::Result<()> {
+        if self.policy == SyncPolicy::Filesystem {
+            self.timed(|| backend.sync_all(root_dir))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The total time spent syncing, summed across tasks.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::Relaxed))
+    }
+
+    fn timed(&self, sync: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        let start = Instant::now();
+        let result = sync();
+        self.elapsed_nanos.fetch_add(
+            start.elapsed().as_nanos().try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        result
+    }
+}
@@ -1,28 +1,100 @@
-use std::{fs::File, io, io::Read};
+use std::{
+    io,
+    io::{ErrorKind::NotFound, Read, Write},
+};
 
-use cfg_if::cfg_if;
-use rand::{RngCore, SeedableRng, TryRngCore};
+use rand::{RngCore, SeedableRng};
 use rand_distr::Normal;
 use rand_xoshiro::Xoshiro256PlusPlus;
 
-use crate::{core::sample_truncated, utils::FastPathBuf};
+use crate::{
+    core::{
+        backend::{Backend, EntryMetadata},
+        durability::Syncer,
+        sample_truncated,
+    },
+    utils::FastPathBuf,
+};
 
 pub trait FileContentsGenerator {
     type State;
 
     fn initialize(&self) -> Self::State;
 
+    /// Creates the file through `backend`, creating its parent directory first
+    /// if it is `retryable` and doesn't exist yet.
     fn create_file(
         &mut self,
+        backend: &dyn Backend,
         file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
         file_num: usize,
         retryable: bool,
         state: &mut Self::State,
-    ) -> io::Result<u64>;
+    ) -> io::Result<FileOutcome>;
+
+    /// Like [`Self::create_file`], but leaves the file untouched if it already
+    /// exists with the expected size.
+    fn resume_file(
+        &mut self,
+        backend: &dyn Backend,
+        file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
+        file_num: usize,
+        retryable: bool,
+        state: &mut Self::State,
+    ) -> io::Result<FileOutcome>;
+
+    /// Writes the contents [`Self::create_file`] would produce to `out`
+    /// instead of a backend.
+    fn write_file(
+        &mut self,
+        out: impl ContentsSink,
+        file_num: usize,
+        retryable: bool,
+        state: &mut Self::State,
+    ) -> io::Result<FileOutcome>;
 
     fn byte_counts_pool_return(self) -> Option<Vec<u64>>;
 }
 
+/// Receives the contents produced by [`FileContentsGenerator::write_file`].
+pub trait ContentsSink {
+    /// Prepares for a file of `len` bytes, returning where its contents go.
+    ///
+    /// Called exactly once per file, before any of its contents are written.
+    fn open(&mut self, len: u64) -> io::Result<impl Write>;
+
+    /// Receives the seed of a non-empty file's contents before [`Self::open`]
+    /// is called.
+    fn seed(&mut self, _: impl FnOnce() -> ContentSeed) {}
+}
+
+impl ContentsSink for io::Sink {
+    fn open(&mut self, _: u64) -> io::Result<impl Write> {
+        Ok(self)
+    }
+}
+
+pub struct FileOutcome {
+    pub bytes: u64,
+    /// The permission bits requested when creating the file, or the file's
+    /// actual permissions if it was reused.
+    pub mode: u32,
+    /// The [`ContentHasher`] digest of the file's contents if requested.
+    pub hash: Option<u64>,
+    pub reused: bool,
+}
+
+/// The permission bits requested by [`File::create`](std::fs::File::create).
+pub const FILE_MODE: u32 = 0o666;
+/// The permission bits requested for empty files.
+const EMPTY_FILE_MODE: u32 = if cfg!(any(not(unix), miri)) {
+    FILE_MODE
+} else {
+    0o664
+};
+
 pub struct NoGeneratedFileContents;
 
 impl FileContentsGenerator for NoGeneratedFileContents {
@@ -31,44 +103,59 @@ impl FileContentsGenerator for NoGeneratedFileContents {
     fn initialize(&self) -> Self::State {}
 
     #[inline]
-    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, backend, sync))
+    )]
     fn create_file(
         &mut self,
+        backend: &dyn Backend,
         file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
+        _: usize,
+        retryable: bool,
+        (): &mut Self::State,
+    ) -> io::Result<FileOutcome> {
+        create_bytes::<Xoshiro256PlusPlus>(
+            backend,
+            file,
+            sync,
+            EntryMetadata {
+                len: 0,
+                mode: EMPTY_FILE_MODE,
+            },
+            BytesKind::Fixed(0),
+            true,
+            retryable,
+        )
+        .map(|_| empty_file(EMPTY_FILE_MODE))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, backend, sync))
+    )]
+    fn resume_file(
+        &mut self,
+        backend: &dyn Backend,
+        file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
         _: usize,
         _: bool,
         (): &mut Self::State,
-    ) -> io::Result<u64> {
-        cfg_if! {
-            if #[cfg(any(not(unix), miri))] {
-                File::create(file).map(|_| 0)
-            } else if #[cfg(target_os = "linux")] {
-                use rustix::fs::{mknodat, FileType, Mode};
-
-                let cstr = file.to_cstr_mut();
-                mknodat(
-                    rustix::fs::CWD,
-                    &*cstr,
-                    FileType::RegularFile,
-                    Mode::RUSR | Mode::WUSR | Mode::RGRP | Mode::WGRP | Mode::ROTH,
-                    0,
-                )
-                .map_err(io::Error::from)
-                .map(|()| 0)
-            } else {
-                use rustix::fs::{openat, OFlags, Mode};
-
-                let cstr = file.to_cstr_mut();
-                openat(
-                    rustix::fs::CWD,
-                    &*cstr,
-                    OFlags::CREATE,
-                    Mode::RUSR | Mode::WUSR | Mode::RGRP | Mode::WGRP | Mode::ROTH,
-                )
-                .map_err(io::Error::from)
-                .map(|_| 0)
-            }
-        }
+    ) -> io::Result<FileOutcome> {
+        resume_bytes::<Xoshiro256PlusPlus>(backend, file, sync, 0, BytesKind::Fixed(0), true)
+    }
+
+    fn write_file(
+        &mut self,
+        mut out: impl ContentsSink,
+        _: usize,
+        _: bool,
+        (): &mut Self::State,
+    ) -> io::Result<FileOutcome> {
+        out.open(0)?;
+        Ok(empty_file(EMPTY_FILE_MODE))
     }
 
     fn byte_counts_pool_return(self) -> Option<Vec<u64>> {
@@ -81,6 +168,7 @@ pub struct OnTheFlyGeneratedFileContents {
     pub num_bytes_distr: Normal<f64>,
     pub seed: u64,
     pub fill_byte: Option<u8>,
+    pub hash: bool,
 }
 
 impl FileContentsGenerator for OnTheFlyGeneratedFileContents {
@@ -93,52 +181,119 @@ impl FileContentsGenerator for OnTheFlyGeneratedFileContents {
     }
 
     #[inline]
-    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace"))]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(backend, sync))
+    )]
     fn create_file(
         &mut self,
+        backend: &dyn Backend,
         file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
         file_num: usize,
         retryable: bool,
         random: &mut Self::State,
-    ) -> io::Result<u64> {
+    ) -> io::Result<FileOutcome> {
+        let Self {
+            ref num_bytes_distr,
+            seed: _,
+            fill_byte,
+            hash,
+        } = *self;
+
+        // The first file of a directory used to be created before its size was
+        // sampled a second time so that a failed attempt which had to be retried
+        // mutated `random` the same way as a successful one. Layouts must stay
+        // reproducible, so the first file's size is still sampled twice.
+        let mut num_bytes = sample_truncated(num_bytes_distr, random);
+        if num_bytes == 0 && !retryable {
+            return NoGeneratedFileContents.create_file(
+                backend,
+                file,
+                sync,
+                file_num,
+                retryable,
+                &mut (),
+            );
+        }
+        if retryable {
+            num_bytes = sample_truncated(num_bytes_distr, random);
+        }
+        Ok(FileOutcome {
+            bytes: num_bytes,
+            mode: FILE_MODE,
+            hash: create_bytes(
+                backend,
+                file,
+                sync,
+                EntryMetadata {
+                    len: num_bytes,
+                    mode: FILE_MODE,
+                },
+                (fill_byte, random),
+                hash,
+                retryable,
+            )?,
+            reused: false,
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(backend, sync))
+    )]
+    fn resume_file(
+        &mut self,
+        backend: &dyn Backend,
+        file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
+        _: usize,
+        retryable: bool,
+        random: &mut Self::State,
+    ) -> io::Result<FileOutcome> {
         let Self {
             ref num_bytes_distr,
             seed: _,
             fill_byte,
+            hash,
         } = *self;
 
+        // Mirror the sampling performed by create_file
+        if retryable {
+            let _ = sample_truncated(num_bytes_distr, random);
+        }
         let num_bytes = sample_truncated(num_bytes_distr, random);
-        if num_bytes > 0 || retryable {
-            File::create(file).and_then(|f| {
-                // To stay deterministic, we need to ensure `random` is mutated in exactly
-                // the same way regardless of whether or not creating the file fails and
-                // needs to be retried. To do this, we always run num_to_generate() twice
-                // for the initial file creation attempt. Thus, the branching looks like
-                // this:
-                //
-                // FAILURE
-                // 1. Call num_to_generate() in initial retry-aware if check
-                // 2. Perform retry by moving to for loop
-                // 3. Call write_random_bytes(num_to_generate())
-                //
-                // SUCCESS
-                // 1. Call num_to_generate() in initial retry-aware if check
-                //    - This value is ignored.
-                // 2. Call write_random_bytes(num_to_generate()) below
-                //    - Notice that num_to_generate can be 0 which is a bummer b/c we can't use
-                //      mknod even though we'd like to.
-                let num_bytes = if retryable {
-                    sample_truncated(num_bytes_distr, random)
-                } else {
-                    num_bytes
-                };
-                write_bytes(f, num_bytes, (fill_byte, random))?;
-                Ok(num_bytes)
-            })
-        } else {
-            NoGeneratedFileContents.create_file(file, file_num, retryable, &mut ())
+        resume_bytes(backend, file, sync, num_bytes, (fill_byte, random), hash)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(out)))]
+    fn write_file(
+        &mut self,
+        out: impl ContentsSink,
+        file_num: usize,
+        retryable: bool,
+        random: &mut Self::State,
+    ) -> io::Result<FileOutcome> {
+        let Self {
+            ref num_bytes_distr,
+            seed: _,
+            fill_byte,
+            hash,
+        } = *self;
+
+        let mut num_bytes = sample_truncated(num_bytes_distr, random);
+        if num_bytes == 0 && !retryable {
+            return NoGeneratedFileContents.write_file(out, file_num, retryable, &mut ());
+        }
+        if retryable {
+            num_bytes = sample_truncated(num_bytes_distr, random);
         }
+        Ok(FileOutcome {
+            bytes: num_bytes,
+            mode: FILE_MODE,
+            hash: write_bytes(out, num_bytes, (fill_byte, random), hash)?,
+            reused: false,
+        })
     }
 
     fn byte_counts_pool_return(self) -> Option<Vec<u64>> {
@@ -151,6 +306,7 @@ pub struct PreDefinedGeneratedFileContents {
     pub byte_counts: Vec<u64>,
     pub seed: u64,
     pub fill_byte: Option<u8>,
+    pub hash: bool,
 }
 
 impl FileContentsGenerator for PreDefinedGeneratedFileContents {
@@ -163,28 +319,111 @@ impl FileContentsGenerator for PreDefinedGeneratedFileContents {
     }
 
     #[inline]
-    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace"))]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(backend, sync))
+    )]
     fn create_file(
         &mut self,
+        backend: &dyn Backend,
         file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
         file_num: usize,
         retryable: bool,
         random: &mut Self::State,
-    ) -> io::Result<u64> {
+    ) -> io::Result<FileOutcome> {
         let Self {
             ref byte_counts,
             seed: _,
             fill_byte,
+            hash,
         } = *self;
 
         let num_bytes = byte_counts[file_num];
-        if num_bytes > 0 {
-            File::create(file)
-                .and_then(|f| write_bytes(f, num_bytes, (fill_byte, random)))
-                .map(|()| num_bytes)
-        } else {
-            NoGeneratedFileContents.create_file(file, file_num, retryable, &mut ())
+        if num_bytes == 0 {
+            return NoGeneratedFileContents.create_file(
+                backend,
+                file,
+                sync,
+                file_num,
+                retryable,
+                &mut (),
+            );
         }
+        Ok(FileOutcome {
+            bytes: num_bytes,
+            mode: FILE_MODE,
+            hash: create_bytes(
+                backend,
+                file,
+                sync,
+                EntryMetadata {
+                    len: num_bytes,
+                    mode: FILE_MODE,
+                },
+                (fill_byte, random),
+                hash,
+                retryable,
+            )?,
+            reused: false,
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(backend, sync))
+    )]
+    fn resume_file(
+        &mut self,
+        backend: &dyn Backend,
+        file: &mut FastPathBuf,
+        sync: Option<&Syncer>,
+        file_num: usize,
+        _: bool,
+        random: &mut Self::State,
+    ) -> io::Result<FileOutcome> {
+        let Self {
+            ref byte_counts,
+            seed: _,
+            fill_byte,
+            hash,
+        } = *self;
+
+        resume_bytes(
+            backend,
+            file,
+            sync,
+            byte_counts[file_num],
+            (fill_byte, random),
+            hash,
+        )
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(out)))]
+    fn write_file(
+        &mut self,
+        out: impl ContentsSink,
+        file_num: usize,
+        retryable: bool,
+        random: &mut Self::State,
+    ) -> io::Result<FileOutcome> {
+        let Self {
+            ref byte_counts,
+            seed: _,
+            fill_byte,
+            hash,
+        } = *self;
+
+        let num_bytes = byte_counts[file_num];
+        if num_bytes == 0 {
+            return NoGeneratedFileContents.write_file(out, file_num, retryable, &mut ());
+        }
+        Ok(FileOutcome {
+            bytes: num_bytes,
+            mode: FILE_MODE,
+            hash: write_bytes(out, num_bytes, (fill_byte, random), hash)?,
+            reused: false,
+        })
     }
 
     fn byte_counts_pool_return(self) -> Option<Vec<u64>> {
@@ -192,6 +431,37 @@ impl FileContentsGenerator for PreDefinedGeneratedFileContents {
     }
 }
 
+/// The state from which a file's contents are generated.
+///
+/// Files created by the same task draw their sizes and contents from a shared
+/// random stream, so the seed captures the stream as it was when the file's
+/// contents were generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentSeed(Seed);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Seed {
+    Random(Xoshiro256PlusPlus),
+    Fixed(u8),
+}
+
+impl ContentSeed {
+    /// Regenerates the first `len` bytes of the file's contents.
+    ///
+    /// Reading the contents advances the seed, so it must be cloned to read
+    /// them more than once.
+    pub fn contents(&mut self, len: u64) -> FileContents<'_> {
+        match self.0 {
+            Seed::Random(ref mut random) => {
+                FileContents::new(len, BytesKind::Random(random), false)
+            }
+            Seed::Fixed(byte) => {
+                FileContents::new::<Xoshiro256PlusPlus>(len, BytesKind::Fixed(byte), false)
+            }
+        }
+    }
+}
+
 enum BytesKind<'a, R> {
     Random(&'a mut R),
     Fixed(u8),
@@ -203,19 +473,245 @@ impl<'a, R> From<(Option<u8>, &'a mut R)> for BytesKind<'a, R> {
     }
 }
 
+/// The number of random bytes generated at a time.
+const BLOCK_SIZE: usize = 8 * 1024;
+
+/// A stream of the contents of a generated file.
+///
+/// Random contents are generated in fixed-size blocks, so the bytes read don't
+/// depend on the size of the buffers they are read into.
+pub struct FileContents<'a> {
+    source: Source<'a>,
+    remaining: u64,
+    hasher: Option<ContentHasher<()>>,
+    /// Holds a generated block while it is read in smaller pieces, only
+    /// allocated once a read needs it.
+    block: Vec<u8>,
+    /// The range of `block` which has been generated but not read yet.
+    start: usize,
+    end: usize,
+}
+
+enum Source<'a> {
+    Random(&'a mut dyn RngCore),
+    Fixed(u8),
+}
+
+impl<'a> FileContents<'a> {
+    fn new<R: RngCore + 'a>(len: u64, kind: impl Into<BytesKind<'a, R>>, hash: bool) -> Self {
+        Self {
+            source: match kind.into() {
+                BytesKind::Random(random) => Source::Random(random),
+                BytesKind::Fixed(byte) => Source::Fixed(byte),
+            },
+            remaining: len,
+            hasher: hash.then(|| ContentHasher::new(())),
+            block: Vec::new(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    /// Reads whatever contents are left so that the random state advances as
+    /// though the whole file had been written, returning its hash if requested.
+    fn finish(mut self) -> io::Result<Option<u64>> {
+        io::copy(&mut self, &mut io::sink())?;
+        Ok(self.hasher.as_ref().map(ContentHasher::finish))
+    }
+}
+
+impl Read for FileContents<'_> {
+    #[allow(clippy::cast_possible_truncation)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Self {
+            ref mut source,
+            ref mut remaining,
+            ref mut hasher,
+            ref mut block,
+            ref mut start,
+            ref mut end,
+        } = *self;
+
+        let available = usize::try_from(*remaining).unwrap_or(usize::MAX);
+        let read = match source {
+            Source::Fixed(byte) => {
+                let read = buf.len().min(available);
+                buf[..read].fill(*byte);
+                read
+            }
+            Source::Random(random) => {
+                if start == end {
+                    let len = available.min(BLOCK_SIZE);
+                    if buf.len() >= len {
+                        random.fill_bytes(&mut buf[..len]);
+                        *remaining -= len as u64;
+                        if let Some(hasher) = hasher {
+                            hasher.update(&buf[..len]);
+                        }
+                        return Ok(len);
+                    }
+                    block.resize(BLOCK_SIZE, 0);
+                    random.fill_bytes(&mut block[..len]);
+                    (*start, *end) = (0, len);
+                }
+                let read = buf.len().min(*end - *start);
+                buf[..read].copy_from_slice(&block[*start..*start + read]);
+                *start += read;
+                read
+            }
+        };
+        *remaining -= read as u64;
+        if let Some(hasher) = hasher {
+            hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+/// Computes the 64-bit FNV-1a hash of everything written to it.
+///
+/// This hash was chosen because it is trivial to reimplement when consuming
+/// manifests and stable across versions.
+pub struct ContentHasher<W> {
+    inner: W,
+    hash: u64,
+}
+
+impl<W> ContentHasher<W> {
+    pub const EMPTY: u64 = 0xcbf2_9ce4_8422_2325;
+
+    pub const fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hash: Self::EMPTY,
+        }
+    }
+
+    pub const fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn update(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            self.hash ^= u64::from(byte);
+            self.hash = self.hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl<W: Write> Write for ContentHasher<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+const fn empty_file(mode: u32) -> FileOutcome {
+    FileOutcome {
+        bytes: 0,
+        mode,
+        hash: Some(ContentHasher::<()>::EMPTY),
+        reused: false,
+    }
+}
+
 #[cfg_attr(
     feature = "tracing",
-    tracing::instrument(level = "trace", skip(file, kind))
+    tracing::instrument(level = "trace", skip(out, kind))
 )]
-fn write_bytes<'a, R: RngCore + 'static>(
-    mut file: File,
+fn write_bytes<'a>(
+    mut out: impl ContentsSink,
     num: u64,
-    kind: impl Into<BytesKind<'a, R>>,
-) -> io::Result<()> {
-    let copied = match kind.into() {
-        BytesKind::Random(random) => io::copy(&mut random.read_adapter().take(num), &mut file),
-        BytesKind::Fixed(byte) => io::copy(&mut io::repeat(byte).take(num), &mut file),
-    }?;
+    kind: impl Into<BytesKind<'a, Xoshiro256PlusPlus>>,
+    hash: bool,
+) -> io::Result<Option<u64>> {
+    let kind = kind.into();
+    if num > 0 {
+        out.seed(|| {
+            ContentSeed(match kind {
+                BytesKind::Random(ref random) => Seed::Random((*random).clone()),
+                BytesKind::Fixed(byte) => Seed::Fixed(byte),
+            })
+        });
+    }
+    let mut contents = FileContents::new(num, kind, hash);
+    let copied = io::copy(&mut contents, &mut out.open(num)?)?;
     debug_assert_eq!(num, copied);
-    Ok(())
+    contents.finish()
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(backend, sync, kind))
+)]
+fn create_bytes<'a, R: RngCore + 'a>(
+    backend: &dyn Backend,
+    file: &mut FastPathBuf,
+    sync: Option<&Syncer>,
+    metadata: EntryMetadata,
+    kind: impl Into<BytesKind<'a, R>>,
+    hash: bool,
+    retryable: bool,
+) -> io::Result<Option<u64>> {
+    let mut contents = FileContents::new(metadata.len, kind, hash);
+    match backend.create_file_in_place(file, &metadata, &mut contents, sync) {
+        Err(e) if retryable && e.kind() == NotFound => {
+            #[cfg(feature = "tracing")]
+            tracing::event!(tracing::Level::TRACE, file = ?file, "Parent directory not created in time");
+
+            if let Some(parent) = file.parent() {
+                backend.create_dir(parent, &EntryMetadata::DIR)?;
+            }
+            backend.create_file_in_place(file, &metadata, &mut contents, sync)?;
+        }
+        result => result?,
+    }
+    contents.finish()
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(backend, sync, kind))
+)]
+fn resume_bytes<'a, R: RngCore + 'a>(
+    backend: &dyn Backend,
+    file: &mut FastPathBuf,
+    sync: Option<&Syncer>,
+    num_bytes: u64,
+    kind: impl Into<BytesKind<'a, R>>,
+    hash: bool,
+) -> io::Result<FileOutcome> {
+    let existing = backend
+        .file_metadata(file)
+        .filter(|metadata| metadata.len == num_bytes);
+    let mut contents = FileContents::new(num_bytes, kind, hash);
+    let mode = if let Some(EntryMetadata { len: _, mode }) = existing {
+        mode
+    } else {
+        backend.create_file_in_place(
+            file,
+            &EntryMetadata {
+                len: num_bytes,
+                mode: FILE_MODE,
+            },
+            &mut contents,
+            sync,
+        )?;
+        FILE_MODE
+    };
+
+    Ok(FileOutcome {
+        bytes: num_bytes,
+        mode,
+        // The random state must still advance as though a reused file had been
+        // written so that subsequent files are identical to those of an
+        // uninterrupted run.
+        hash: contents.finish()?,
+        reused: existing.is_some(),
+    })
 }
@@ -1,17 +1,63 @@
-use std::{fs::create_dir_all, io, io::ErrorKind::NotFound};
+use std::{io, path::Path, sync::Arc, time::Instant};
 
-use error_stack::{Report, Result, ResultExt};
+use error_stack::{Result, ResultExt};
 
 use crate::{
-    core::file_contents::FileContentsGenerator,
+    core::{
+        archive::{Archive, TaskArchive},
+        backend::{Backend, EntryMetadata},
+        digest::TreeDigest,
+        durability::Syncer,
+        file_contents::{FileContentsGenerator, FileOutcome},
+        journal::{Checkpoint, Journal},
+        manifest::{Manifest, TaskManifest},
+        observer::{CompletedTask, Observer},
+        plan::{Planner, SeedSink, TaskPlanner},
+        verify::{TaskVerifier, Verifier},
+    },
     utils::{FastPathBuf, with_dir_name, with_file_name},
 };
 
+/// What a task does with the entries it is assigned.
+#[derive(Debug, Clone)]
+pub enum TaskMode {
+    /// Write the entries through a backend.
+    Create(Arc<dyn Backend>),
+    /// Only write missing or incomplete files through a backend.
+    Resume(Arc<dyn Backend>),
+    /// Compute the entries without touching the filesystem.
+    DryRun,
+    /// Compare the entries against those on disk.
+    Verify(Arc<Verifier>),
+    /// Stream the entries into an archive without touching the filesystem.
+    ///
+    /// The ticket orders the task's entries within the archive and is
+    /// reserved when the task is queued.
+    Archive { archive: Arc<Archive>, ticket: u64 },
+    /// Hand the entries to a consumer without touching the filesystem.
+    ///
+    /// The ticket orders the task's entries among those sent to the consumer
+    /// and is reserved when the task is queued.
+    Plan { planner: Arc<Planner>, ticket: u64 },
+}
+
 pub struct GeneratorTaskParams<G: FileContentsGenerator> {
     pub target_dir: FastPathBuf,
     pub num_files: u64,
     pub num_dirs: usize,
     pub file_offset: u64,
+    pub mode: TaskMode,
+    pub manifest: Option<Arc<Manifest>>,
+    pub digest: Option<Arc<TreeDigest>>,
+    pub observer: Option<Arc<dyn Observer>>,
+    pub sync: Option<Arc<Syncer>>,
+    pub journal: Option<Arc<Journal>>,
+    /// The task's position in queue order, numbered when it is queued if a
+    /// journal is kept.
+    pub task: u64,
+    /// Orders the task's records within the manifest and is reserved when the
+    /// task is queued.
+    pub manifest_ticket: u64,
     pub file_contents: G,
 }
 
@@ -19,6 +65,9 @@ pub struct GeneratorTaskOutcome {
     pub files_generated: u64,
     pub dirs_generated: usize,
     pub bytes_generated: u64,
+    pub files_reused: u64,
+    pub files_empty: u64,
+    pub digest: u64,
 
     pub pool_return_file: FastPathBuf,
     pub pool_return_byte_counts: Option<Vec<u64>>,
@@ -26,7 +75,10 @@ pub struct GeneratorTaskOutcome {
 
 #[cfg_attr(
     feature = "tracing",
-    tracing::instrument(level = "debug", skip(file_contents))
+    tracing::instrument(
+        level = "debug",
+        skip(manifest, digest, observer, sync, journal, file_contents)
+    )
 )]
 pub fn create_files_and_dirs(
     GeneratorTaskParams {
@@ -34,29 +86,275 @@ pub fn create_files_and_dirs(
         num_files,
         num_dirs,
         file_offset,
+        mode,
+        manifest,
+        digest,
+        observer,
+        sync,
+        journal,
+        task,
+        manifest_ticket,
         mut file_contents,
     }: GeneratorTaskParams<impl FileContentsGenerator>,
 ) -> Result<GeneratorTaskOutcome, io::Error> {
-    create_dirs(num_dirs, &mut target_dir)?;
-    create_files(num_files, file_offset, &mut target_dir, &mut file_contents).map(|bytes_written| {
-        GeneratorTaskOutcome {
-            files_generated: num_files,
-            dirs_generated: num_dirs,
-            bytes_generated: bytes_written,
-
-            pool_return_file: target_dir,
-            pool_return_byte_counts: file_contents.byte_counts_pool_return(),
+    let start = observer.is_some().then(Instant::now);
+    let mut records = Records {
+        manifest: manifest
+            .as_deref()
+            .map(|manifest| manifest.task(manifest_ticket)),
+        digest: digest.as_deref().map(|d| (d, 0)),
+        observer: observer.as_deref().filter(|o| o.observes_files()),
+        empty_files: 0,
+    };
+
+    let (bytes_written, files_reused) = match mode {
+        TaskMode::Create(backend) => {
+            create_dirs(&*backend, num_dirs, &mut target_dir, &mut records)?;
+            let bytes = create_files(
+                &*backend,
+                num_files,
+                file_offset,
+                &mut target_dir,
+                &mut file_contents,
+                sync.as_deref(),
+                &mut records,
+            )?;
+            sync_dir(&*backend, &target_dir, sync.as_deref())?;
+            (bytes, 0)
+        }
+        TaskMode::Resume(backend) => {
+            create_dirs(&*backend, num_dirs, &mut target_dir, &mut records)?;
+            let outcome = resume_files(
+                &*backend,
+                num_files,
+                file_offset,
+                &mut target_dir,
+                &mut file_contents,
+                sync.as_deref(),
+                &mut records,
+            )?;
+            sync_dir(&*backend, &target_dir, sync.as_deref())?;
+            outcome
+        }
+        TaskMode::DryRun => {
+            plan_dirs(num_dirs, &mut target_dir, Plan::DryRun, &mut records)?;
+            let bytes = plan_files(
+                num_files,
+                file_offset,
+                &mut target_dir,
+                &mut file_contents,
+                Plan::DryRun,
+                &mut records,
+            )?;
+            (bytes, 0)
+        }
+        TaskMode::Verify(verifier) => {
+            let mut verifier = verifier.task();
+            plan_dirs(
+                num_dirs,
+                &mut target_dir,
+                Plan::Verify(&mut verifier),
+                &mut records,
+            )?;
+            let bytes = plan_files(
+                num_files,
+                file_offset,
+                &mut target_dir,
+                &mut file_contents,
+                Plan::Verify(&mut verifier),
+                &mut records,
+            )?;
+            verifier
+                .finish(&target_dir, num_dirs, file_offset + num_files)
+                .attach_printable_lazy(|| {
+                    format!("Failed to look for extra entries in {target_dir:?}")
+                })?;
+            (bytes, 0)
+        }
+        TaskMode::Archive { archive, ticket } => {
+            let mut archive = archive.task(ticket);
+            plan_dirs(
+                num_dirs,
+                &mut target_dir,
+                Plan::Archive(&mut archive),
+                &mut records,
+            )?;
+            let bytes = plan_files(
+                num_files,
+                file_offset,
+                &mut target_dir,
+                &mut file_contents,
+                Plan::Archive(&mut archive),
+                &mut records,
+            )?;
+            // Hand the archive over to the next task
+            drop(archive);
+            (bytes, 0)
         }
+        TaskMode::Plan { planner, ticket } => {
+            let mut planner = planner.task(ticket);
+            plan_dirs(
+                num_dirs,
+                &mut target_dir,
+                Plan::Walk(&mut planner),
+                &mut records,
+            )?;
+            let bytes = plan_files(
+                num_files,
+                file_offset,
+                &mut target_dir,
+                &mut file_contents,
+                Plan::Walk(&mut planner),
+                &mut records,
+            )?;
+            // Hand the planner over to the next task
+            drop(planner);
+            (bytes, 0)
+        }
+    };
+
+    let (digest, empty_files) = records
+        .finish()
+        .attach_printable("Failed to write to manifest")?;
+    if let Some(journal) = journal {
+        journal
+            .complete(task, Checkpoint {
+                tasks: 1,
+                bytes: bytes_written,
+                empty_files,
+                digest,
+            })
+            .attach_printable("Failed to write to journal")?;
+    }
+    if let (Some(observer), Some(start)) = (&observer, start) {
+        observer.task_completed(&CompletedTask {
+            dir: &target_dir,
+            files: num_files,
+            dirs: num_dirs as u64,
+            bytes: bytes_written,
+            elapsed: start.elapsed(),
+        });
+    }
+
+    Ok(GeneratorTaskOutcome {
+        files_generated: num_files,
+        dirs_generated: num_dirs,
+        bytes_generated: bytes_written,
+        files_reused,
+        files_empty: empty_files,
+        digest,
+
+        pool_return_file: target_dir,
+        pool_return_byte_counts: file_contents.byte_counts_pool_return(),
     })
 }
 
-#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace"))]
-fn create_dirs(num_dirs: usize, dir: &mut FastPathBuf) -> Result<(), io::Error> {
+/// Accounts for a task completed by a previous run without touching its
+/// entries.
+///
+/// The task's bytes, empty files, and digest are only known in aggregate, so
+/// they are added from the journal once the run completes.
+pub fn skip_files_and_dirs(
+    GeneratorTaskParams {
+        target_dir,
+        num_files,
+        num_dirs,
+        file_contents,
+        ..
+    }: GeneratorTaskParams<impl FileContentsGenerator>,
+) -> GeneratorTaskOutcome {
+    GeneratorTaskOutcome {
+        files_generated: num_files,
+        dirs_generated: num_dirs,
+        bytes_generated: 0,
+        files_reused: num_files,
+        files_empty: 0,
+        digest: 0,
+
+        pool_return_file: target_dir,
+        pool_return_byte_counts: file_contents.byte_counts_pool_return(),
+    }
+}
+
+/// Tallies each entry a task produces and passes it on to the optional
+/// consumers of entries.
+struct Records<'a> {
+    manifest: Option<TaskManifest<'a>>,
+    digest: Option<(&'a TreeDigest, u64)>,
+    observer: Option<&'a dyn Observer>,
+    empty_files: u64,
+}
+
+impl Records<'_> {
+    fn dir(&mut self, path: &Path) -> io::Result<()> {
+        let Self {
+            manifest,
+            digest,
+            observer: _,
+            empty_files: _,
+        } = self;
+        if let Some((tree, digest)) = digest {
+            *digest = digest.wrapping_add(tree.dir(path));
+        }
+        manifest
+            .as_mut()
+            .map_or(Ok(()), |manifest| manifest.dir(path))
+    }
+
+    fn file(&mut self, path: &Path, outcome: &FileOutcome) -> io::Result<()> {
+        let Self {
+            manifest,
+            digest,
+            observer,
+            empty_files,
+        } = self;
+        *empty_files += u64::from(outcome.bytes == 0);
+        if let Some(observer) = observer {
+            observer.file_created(path, outcome.bytes);
+        }
+        if let Some((tree, digest)) = digest {
+            *digest = digest.wrapping_add(tree.file(path, outcome.hash.unwrap_or_default()));
+        }
+        manifest
+            .as_mut()
+            .map_or(Ok(()), |manifest| manifest.file(path, outcome))
+    }
+
+    /// Writes the remaining records, returning the task's digest and number of
+    /// empty files.
+    fn finish(self) -> io::Result<(u64, u64)> {
+        let Self {
+            manifest,
+            digest,
+            observer: _,
+            empty_files,
+        } = self;
+        if let Some(manifest) = manifest {
+            manifest.finish()?;
+        }
+        Ok((digest.map_or(0, |(_, digest)| digest), empty_files))
+    }
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(backend, records))
+)]
+fn create_dirs(
+    backend: &dyn Backend,
+    num_dirs: usize,
+    dir: &mut FastPathBuf,
+    records: &mut Records,
+) -> Result<(), io::Error> {
     for i in 0..num_dirs {
         let dir = with_dir_name(i, |s| dir.push(s));
 
-        create_dir_all(&dir)
+        backend
+            .create_dir(&dir, &EntryMetadata::DIR)
             .attach_printable_lazy(|| format!("Failed to create directory {dir:?}"))?;
+        records
+            .dir(&dir)
+            .attach_printable("Failed to write to manifest")?;
 
         dir.pop();
     }
@@ -65,56 +363,201 @@ fn create_dirs(num_dirs: usize, dir: &mut FastPathBuf) -> Result<(), io::Error>
 
 #[cfg_attr(
     feature = "tracing",
-    tracing::instrument(level = "trace", skip(contents))
+    tracing::instrument(level = "trace", skip(backend, contents, sync, records))
 )]
 fn create_files(
+    backend: &dyn Backend,
     num_files: u64,
     offset: u64,
     file: &mut FastPathBuf,
     contents: &mut impl FileContentsGenerator,
+    sync: Option<&Syncer>,
+    records: &mut Records,
 ) -> Result<u64, io::Error> {
     let mut state = contents.initialize();
     let mut bytes_written = 0;
 
-    let mut start_file = 0;
-    if num_files > 0 {
-        let mut guard = with_file_name(offset, |s| file.push(s));
-
-        match contents.create_file(&mut guard, 0, true, &mut state) {
-            Ok(bytes) => {
-                bytes_written += bytes;
-                start_file += 1;
-                guard.pop();
-            }
-            Err(e) => {
-                if e.kind() == NotFound {
-                    #[cfg(feature = "tracing")]
-                    tracing::event!(tracing::Level::TRACE, file = ?guard, "Parent directory not created in time");
-
-                    guard.pop();
-                    create_dir_all(&*file)
-                        .attach_printable_lazy(|| format!("Failed to create directory {file:?}"))?;
-                } else {
-                    return Err(Report::new(e))
-                        .attach_printable_lazy(|| format!("Failed to create file {file:?}"));
-                }
-            }
-        }
-    }
-    for i in start_file..num_files {
+    for i in 0..num_files {
         let mut file = with_file_name(i + offset, |s| file.push(s));
 
-        bytes_written += contents
+        // The parent directory may not exist yet when creating the first file, in
+        // which case it is created before the file is retried
+        let outcome = contents
             .create_file(
+                backend,
                 &mut file,
+                sync,
                 i.try_into().unwrap_or(usize::MAX),
-                false,
+                i == 0,
                 &mut state,
             )
             .attach_printable_lazy(|| format!("Failed to create file {file:?}"))?;
+        bytes_written += outcome.bytes;
+        records
+            .file(&file, &outcome)
+            .attach_printable("Failed to write to manifest")?;
 
         file.pop();
     }
 
     Ok(bytes_written)
 }
+
+/// Flushes a task's directory once its files and subdirectories exist.
+fn sync_dir(backend: &dyn Backend, dir: &Path, sync: Option<&Syncer>) -> Result<(), io::Error> {
+    if let Some(sync) = sync {
+        sync.dir(backend, dir)
+            .attach_printable_lazy(|| format!("Failed to sync directory {dir:?}"))?;
+    }
+    Ok(())
+}
+
+/// Regenerates missing or incomplete files, returning the number of bytes in
+/// the directory's files and how many of those files were reused.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(backend, contents, sync, records))
+)]
+fn resume_files(
+    backend: &dyn Backend,
+    num_files: u64,
+    offset: u64,
+    file: &mut FastPathBuf,
+    contents: &mut impl FileContentsGenerator,
+    sync: Option<&Syncer>,
+    records: &mut Records,
+) -> Result<(u64, u64), io::Error> {
+    let mut state = contents.initialize();
+    let mut bytes = 0;
+    let mut files_reused = 0;
+
+    if num_files > 0 {
+        backend
+            .create_dir(file, &EntryMetadata::DIR)
+            .attach_printable_lazy(|| format!("Failed to create directory {file:?}"))?;
+    }
+    for i in 0..num_files {
+        let mut file = with_file_name(i + offset, |s| file.push(s));
+
+        let outcome = contents
+            .resume_file(
+                backend,
+                &mut file,
+                sync,
+                i.try_into().unwrap_or(usize::MAX),
+                i == 0,
+                &mut state,
+            )
+            .attach_printable_lazy(|| format!("Failed to create file {file:?}"))?;
+        bytes += outcome.bytes;
+        files_reused += u64::from(outcome.reused);
+        records
+            .file(&file, &outcome)
+            .attach_printable("Failed to write to manifest")?;
+
+        file.pop();
+    }
+
+    Ok((bytes, files_reused))
+}
+
+/// What is done with planned entries besides recording them.
+enum Plan<'a, 'b> {
+    DryRun,
+    Verify(&'a mut TaskVerifier<'b>),
+    Archive(&'a mut TaskArchive<'b>),
+    Walk(&'a mut TaskPlanner<'b>),
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(plan, records))
+)]
+fn plan_dirs(
+    num_dirs: usize,
+    dir: &mut FastPathBuf,
+    mut plan: Plan,
+    records: &mut Records,
+) -> Result<(), io::Error> {
+    for i in 0..num_dirs {
+        let dir = with_dir_name(i, |s| dir.push(s));
+
+        match plan {
+            Plan::DryRun => {}
+            Plan::Verify(ref mut verifier) => verifier
+                .dir(&dir)
+                .attach_printable_lazy(|| format!("Failed to verify directory {dir:?}"))?,
+            Plan::Archive(ref mut archive) => archive
+                .dir(&dir)
+                .attach_printable_lazy(|| format!("Failed to archive directory {dir:?}"))?,
+            Plan::Walk(ref mut planner) => planner
+                .dir(&dir)
+                .attach_printable_lazy(|| format!("Failed to plan directory {dir:?}"))?,
+        }
+        records
+            .dir(&dir)
+            .attach_printable("Failed to write to manifest")?;
+
+        dir.pop();
+    }
+    Ok(())
+}
+
+/// Computes the files that would be created, optionally comparing them
+/// against those on disk, archiving them, or handing them to a planner, and
+/// returns the number of bytes they contain.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(contents, plan, records))
+)]
+fn plan_files(
+    num_files: u64,
+    offset: u64,
+    file: &mut FastPathBuf,
+    contents: &mut impl FileContentsGenerator,
+    mut plan: Plan,
+    records: &mut Records,
+) -> Result<u64, io::Error> {
+    let mut state = contents.initialize();
+    let mut bytes = 0;
+
+    for i in 0..num_files {
+        let file = with_file_name(i + offset, |s| file.push(s));
+
+        let file_num = i.try_into().unwrap_or(usize::MAX);
+        let mut content_seed = None;
+        let expected = if let Plan::Archive(ref mut archive) = plan {
+            archive
+                .file(&file, |sink| {
+                    contents.write_file(sink, file_num, i == 0, &mut state)
+                })
+                .attach_printable_lazy(|| format!("Failed to archive file {file:?}"))?
+        } else {
+            let expected = if let Plan::Walk(_) = plan {
+                let sink = SeedSink(&mut content_seed);
+                contents.write_file(sink, file_num, i == 0, &mut state)
+            } else {
+                contents.write_file(io::sink(), file_num, i == 0, &mut state)
+            };
+            expected
+                .attach_printable_lazy(|| format!("Failed to compute contents of file {file:?}"))?
+        };
+        match plan {
+            Plan::Verify(ref mut verifier) => verifier
+                .file(&file, &expected)
+                .attach_printable_lazy(|| format!("Failed to verify file {file:?}"))?,
+            Plan::Walk(ref mut planner) => planner
+                .file(&file, expected.bytes, content_seed)
+                .attach_printable_lazy(|| format!("Failed to plan file {file:?}"))?,
+            Plan::DryRun | Plan::Archive(_) => {}
+        }
+        bytes += expected.bytes;
+        records
+            .file(&file, &expected)
+            .attach_printable("Failed to write to manifest")?;
+
+        file.pop();
+    }
+
+    Ok(bytes)
+}
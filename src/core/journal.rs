@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::{self, ErrorKind},
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// The name of the journal within the root directory.
+///
+/// Generated entries are always numeric, so this can't collide with them.
+pub const JOURNAL_NAME: &str = ".ftzz-journal";
+
+/// The number of newly completed tasks after which the journal is rewritten.
+const SAVE_INTERVAL: u64 = 1 << 10;
+
+/// Records which tasks have completed so that resumed runs can skip them.
+///
+/// Tasks are numbered in the order they are queued, which only depends on the
+/// tree's parameters. Only the number of leading tasks which have all
+/// completed is saved along with their combined totals, so the journal stays
+/// the same size no matter how large the tree is.
+pub struct Journal {
+    path: PathBuf,
+    fingerprint: u64,
+    /// The tasks completed by a previous run.
+    skipped: Checkpoint,
+    next_task: AtomicU64,
+    state: Mutex<State>,
+}
+
+/// The combined totals of a run of tasks.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub tasks: u64,
+    pub bytes: u64,
+    pub empty_files: u64,
+    /// The wrapping sum of the tasks' digests.
+    pub digest: u64,
+}
+
+struct State {
+    checkpoint: Checkpoint,
+    /// The number of tasks in the last saved checkpoint.
+    saved: u64,
+    /// Tasks which completed before some of the tasks queued ahead of them.
+    completed: BTreeMap<u64, Checkpoint>,
+}
+
+impl AddAssign for Checkpoint {
+    fn add_assign(&mut self, rhs: Self) {
+        self.tasks += rhs.tasks;
+        self.bytes += rhs.bytes;
+        self.empty_files += rhs.empty_files;
+        self.digest = self.digest.wrapping_add(rhs.digest);
+    }
+}
+
+impl fmt::Debug for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Journal")
+            .field("path", &self.path)
+            .field("skipped_tasks", &self.skipped.tasks)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Journal {
+    /// Opens the journal of the tree in `root_dir`, loading the tasks
+    /// completed by a previous run with the same `fingerprint` if `load` is
+    /// set.
+    pub fn open(root_dir: &Path, fingerprint: u64, load: bool) -> io::Result<Self> {
+        let path = root_dir.join(JOURNAL_NAME);
+        let skipped = if load {
+            match fs::read_to_string(&path) {
+                Ok(journal) => parse(&journal, fingerprint)
+                    .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "Malformed journal"))?,
+                Err(e) if e.kind() == ErrorKind::NotFound => Checkpoint::default(),
+                Err(e) => return Err(e),
+            }
+        } else {
+            Checkpoint::default()
+        };
+
+        Ok(Self {
+            path,
+            fingerprint,
+            skipped,
+            next_task: AtomicU64::new(0),
+            state: Mutex::new(State {
+                checkpoint: skipped,
+                saved: skipped.tasks,
+                completed: BTreeMap::new(),
+            }),
+        })
+    }
+
+    /// Numbers the next task, which must be called in queue order.
+    pub fn reserve(&self) -> u64 {
+        self.next_task.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Whether a previous run already completed the task.
+    pub const fn is_complete(&self, task: u64) -> bool {
+        task < self.skipped.tasks
+    }
+
+    /// The totals of the tasks completed by a previous run.
+    pub const fn skipped(&self) -> Checkpoint {
+        self.skipped
+    }
+
+    /// Records the task's totals, saving the journal every so often.
+    pub fn complete(&self, task: u64, totals: Checkpoint) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let State {
+            ref mut checkpoint,
+            ref mut saved,
+            ref mut completed,
+        } = *state;
+
+        completed.insert(task, totals);
+        while let Some(entry) = completed.first_entry()
+            && *entry.key() == checkpoint.tasks
+        {
+            *checkpoint += entry.remove();
+        }
+
+        // Saves hold the lock so that they can't overwrite each other
+        if checkpoint.tasks - *saved >= SAVE_INTERVAL {
+            *saved = checkpoint.tasks;
+            let result = self.save(checkpoint);
+            drop(state);
+            result
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Removes the journal once the tree is complete or otherwise saves the
+    /// tasks completed so far.
+    pub fn finish(&self, tree_complete: bool) -> io::Result<()> {
+        let state = self.state.lock().unwrap();
+        if tree_complete {
+            match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        } else if state.checkpoint.tasks > state.saved {
+            self.save(&state.checkpoint)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn save(
+        &self,
+        &Checkpoint {
+            tasks,
+            bytes,
+            empty_files,
+            digest,
+        }: &Checkpoint,
+    ) -> io::Result<()> {
+        // Replace the journal atomically so that a crash never leaves it half written
+        let tmp = self.path.with_extension("tmp");
+        fs::write(
+            &tmp,
+            format!(
+                "v1 {:016x} {tasks} {bytes} {empty_files} {digest:016x}\n",
+                self.fingerprint
+            ),
+        )?;
+        fs::rename(tmp, &self.path)
+    }
+}
+
+/// Reads a saved checkpoint, ignoring it if it belongs to a different tree.
+fn parse(journal: &str, fingerprint: u64) -> Option<Checkpoint> {
+    let mut fields = journal.trim_end().split(' ');
+    if fields.next()? != "v1" {
+        return None;
+    }
+    if u64::from_str_radix(fields.next()?, 16).ok()? != fingerprint {
+        return Some(Checkpoint::default());
+    }
+    let checkpoint = Checkpoint {
+        tasks: fields.next()?.parse().ok()?,
+        bytes: fields.next()?.parse().ok()?,
+        empty_files: fields.next()?.parse().ok()?,
+        digest: u64::from_str_radix(fields.next()?, 16).ok()?,
+    };
+    fields.next().is_none().then_some(checkpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoint_only_covers_leading_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::open(dir.path(), 7, true).unwrap();
+        let task = |bytes| Checkpoint {
+            tasks: 1,
+            bytes,
+            empty_files: u64::from(bytes == 0),
+            digest: bytes,
+        };
+
+        for _ in 0..3 {
+            journal.reserve();
+        }
+        journal.complete(1, task(10)).unwrap();
+        journal.complete(2, task(0)).unwrap();
+        journal.finish(false).unwrap();
+        assert!(!dir.path().join(JOURNAL_NAME).exists());
+
+        journal.complete(0, task(5)).unwrap();
+        journal.finish(false).unwrap();
+        let resumed = Journal::open(dir.path(), 7, true).unwrap();
+        assert_eq!(resumed.skipped(), Checkpoint {
+            tasks: 3,
+            bytes: 15,
+            empty_files: 1,
+            digest: 15,
+        });
+        assert!(resumed.is_complete(2));
+        assert!(!resumed.is_complete(3));
+
+        let other_tree = Journal::open(dir.path(), 8, true).unwrap();
+        assert_eq!(other_tree.skipped(), Checkpoint::default());
+
+        resumed.finish(true).unwrap();
+        assert!(!dir.path().join(JOURNAL_NAME).exists());
+    }
+}
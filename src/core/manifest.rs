@@ -0,0 +1,260 @@
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Condvar, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use cfg_if::cfg_if;
+
+use crate::{core::file_contents::FileOutcome, utils::push_json_string};
+
+/// The serialization format of a manifest's records.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ManifestFormat {
+    /// One JSON object per line.
+    #[default]
+    JsonLines,
+    /// Comma separated values with a header row.
+    Csv,
+}
+
+/// The number of bytes of records a task buffers before writing them out.
+const FLUSH_THRESHOLD: usize = 64 * 1024;
+
+/// A sink for records describing every generated entry.
+///
+/// Each task reserves a ticket when it is queued and only writes its records
+/// once every earlier task has written its own, so records appear in the
+/// scheduler's order. Waiting can't deadlock for the same reason it can't in
+/// [`Archive`](crate::core::Archive).
+#[derive(Debug)]
+pub struct Manifest {
+    output: Mutex<OrderedOutput>,
+    /// Signalled whenever a task hands the manifest over to the next ticket.
+    turn: Condvar,
+    format: ManifestFormat,
+    root_dir: PathBuf,
+    umask: u32,
+    tickets: AtomicU64,
+}
+
+#[derive(Debug)]
+struct OrderedOutput {
+    writer: BufWriter<File>,
+    /// The ticket of the task currently allowed to write.
+    next: u64,
+}
+
+impl Manifest {
+    pub fn create(path: &Path, format: ManifestFormat, root_dir: PathBuf) -> io::Result<Self> {
+        let mut output = BufWriter::new(File::create(path)?);
+        if format == ManifestFormat::Csv {
+            output.write_all(b"path,type,size,mode,hash\n")?;
+        }
+
+        Ok(Self {
+            output: Mutex::new(OrderedOutput {
+                writer: output,
+                next: 0,
+            }),
+            turn: Condvar::new(),
+            format,
+            root_dir,
+            umask: umask(),
+            tickets: AtomicU64::new(0),
+        })
+    }
+
+    /// Reserves the position of the next task's records in the manifest.
+    pub fn reserve(&self) -> u64 {
+        self.tickets.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub const fn task(&self, ticket: u64) -> TaskManifest<'_> {
+        TaskManifest {
+            manifest: self,
+            ticket,
+            records: String::new(),
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.output.lock().unwrap().writer.flush()
+    }
+
+    /// Blocks until every task before `ticket` has written its records.
+    fn wait_for(&self, ticket: u64) -> MutexGuard<'_, OrderedOutput> {
+        let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        while output.next != ticket {
+            output = self
+                .turn
+                .wait(output)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        output
+    }
+}
+
+/// Collects the records of a single task.
+///
+/// Records are buffered until they outgrow [`FLUSH_THRESHOLD`], at which
+/// point the task waits for its turn to write them. The turn lasts until the
+/// task hands the manifest over to the next task by being dropped.
+pub struct TaskManifest<'a> {
+    manifest: &'a Manifest,
+    ticket: u64,
+    records: String,
+}
+
+impl TaskManifest<'_> {
+    pub fn dir(&mut self, path: &Path) -> io::Result<()> {
+        let mode = 0o777 & !self.manifest.umask;
+        self.record(path, "dir", None, mode, None)
+    }
+
+    pub fn file(&mut self, path: &Path, outcome: &FileOutcome) -> io::Result<()> {
+        let &FileOutcome {
+            bytes,
+            mode,
+            hash,
+            reused,
+        } = outcome;
+        // Reused files report the permissions they actually have
+        let mode = if reused {
+            mode
+        } else {
+            mode & !self.manifest.umask
+        };
+        self.record(path, "file", Some(bytes), mode, hash)
+    }
+
+    /// Writes the remaining records.
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        self.write_records()
+    }
+
+    fn write_records(&mut self) -> io::Result<()> {
+        let Self {
+            manifest,
+            ticket,
+            ref mut records,
+        } = *self;
+        manifest
+            .wait_for(ticket)
+            .writer
+            .write_all(records.as_bytes())?;
+        records.clear();
+        Ok(())
+    }
+
+    fn record(
+        &mut self,
+        path: &Path,
+        kind: &str,
+        size: Option<u64>,
+        mode: u32,
+        hash: Option<u64>,
+    ) -> io::Result<()> {
+        let Self {
+            manifest:
+                &Manifest {
+                    format,
+                    ref root_dir,
+                    ..
+                },
+            ticket: _,
+            ref mut records,
+        } = *self;
+        let path = path
+            .strip_prefix(root_dir)
+            .unwrap_or(path)
+            .to_string_lossy();
+
+        // Writing to a String cannot fail
+        let _ = match format {
+            ManifestFormat::JsonLines => {
+                records.push_str("{\"path\":");
+                push_json_string(records, &path);
+                let _ = write!(records, ",\"type\":\"{kind}\"");
+                if let Some(size) = size {
+                    let _ = write!(records, ",\"size\":{size}");
+                }
+                let _ = write!(records, ",\"mode\":\"{mode:o}\"");
+                if let Some(hash) = hash {
+                    let _ = write!(records, ",\"hash\":\"{hash:016x}\"");
+                }
+                writeln!(records, "}}")
+            }
+            ManifestFormat::Csv => {
+                push_csv_field(records, &path);
+                let _ = write!(records, ",{kind},");
+                if let Some(size) = size {
+                    let _ = write!(records, "{size}");
+                }
+                let _ = write!(records, ",{mode:o},");
+                if let Some(hash) = hash {
+                    let _ = write!(records, "{hash:016x}");
+                }
+                writeln!(records)
+            }
+        };
+
+        if records.len() >= FLUSH_THRESHOLD {
+            self.write_records()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for TaskManifest<'_> {
+    fn drop(&mut self) {
+        // Hand over the manifest even if this task failed so later tasks aren't
+        // left waiting forever
+        let mut output = self.manifest.wait_for(self.ticket);
+        output.next += 1;
+        drop(output);
+        self.manifest.turn.notify_all();
+    }
+}
+
+fn push_csv_field(out: &mut String, s: &str) {
+    if s.contains([',', '"', '\n', '\r']) {
+        out.push('"');
+        out.push_str(&s.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(s);
+    }
+}
+
+/// Retrieves the process's umask which determines the final permissions of
+/// created entries, falling back to recording the requested permissions where
+/// it can't be read.
+///
+/// Elsewhere the umask can only be read by briefly changing it, which would
+/// give files created by other threads in the meantime the wrong permissions.
+fn umask() -> u32 {
+    cfg_if! {
+        if #[cfg(all(target_os = "linux", not(miri)))] {
+            std::fs::read_to_string("/proc/self/status")
+                .ok()
+                .and_then(|status| {
+                    let mask = status.lines().find_map(|line| line.strip_prefix("Umask:"))?;
+                    u32::from_str_radix(mask.trim(), 8).ok()
+                })
+                .unwrap_or(0)
+        } else {
+            0
+        }
+    }
+}
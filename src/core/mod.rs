@@ -1,12 +1,38 @@
+pub use archive::{Archive, ArchiveFormat, ArchiveOutput, ZipCompression};
+pub use backend::{Backend, Entry, EntryMetadata, Filesystem, MemoryBackend};
+pub use cancel::{CancellationToken, StopCondition};
+pub use digest::TreeDigest;
+pub use durability::{SyncPolicy, Syncer};
+pub use file_contents::{ContentHasher, ContentSeed, FileContents};
+pub use files::TaskMode;
+pub use journal::{Checkpoint, Journal};
+pub use manifest::{Manifest, ManifestFormat};
+pub use observer::{CompletedTask, Observer};
+pub use plan::{EntryKind, PlannedEntry, Planner};
+pub use progress::{Progress, ProgressTarget};
 use rand::Rng;
 use rand_distr::{Distribution, Normal};
-pub use scheduler::{GeneratorStats, run};
+pub use scheduler::{GeneratorStats, run, run_flat};
 pub use tasks::{DynamicGenerator, GeneratorBytes, StaticGenerator};
+pub use throttle::{Rates, Throttle, Throttled};
+pub use verify::{Discrepancy, Verifier};
 
+mod archive;
+mod backend;
+mod cancel;
+mod digest;
+mod durability;
 mod file_contents;
 mod files;
+mod journal;
+mod manifest;
+mod observer;
+mod plan;
+mod progress;
 mod scheduler;
 mod tasks;
+mod throttle;
+mod verify;
 
 #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace"))]
 pub fn truncatable_normal(mean: f64) -> Normal<f64> {
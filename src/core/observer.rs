@@ -0,0 +1,37 @@
+use std::{fmt::Debug, path::Path, time::Duration};
+
+/// Receives events as the tree is generated.
+///
+/// Callbacks are invoked from the worker threads which produce the entries,
+/// so implementations should be cheap and must synchronize any shared state.
+pub trait Observer: Debug + Send + Sync {
+    /// Called once a task has produced all of a directory's entries.
+    fn task_completed(&self, task: &CompletedTask<'_>) {
+        let _ = task;
+    }
+
+    /// Whether [`Observer::file_created`] should be invoked.
+    ///
+    /// Per-file callbacks are skipped entirely unless this returns `true`.
+    fn observes_files(&self) -> bool {
+        false
+    }
+
+    /// Called after each file is produced.
+    fn file_created(&self, path: &Path, bytes: u64) {
+        let _ = (path, bytes);
+    }
+}
+
+/// Describes the entries produced by a single task.
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct CompletedTask<'a> {
+    /// The directory in which the task produced its entries.
+    pub dir: &'a Path,
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+    /// How long the task took to produce its entries.
+    pub elapsed: Duration,
+}
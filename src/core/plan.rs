@@ -0,0 +1,169 @@
+use std::{
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        Condvar, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+};
+
+use crate::core::file_contents::{ContentSeed, ContentsSink};
+
+/// The number of planned entries buffered ahead of the consumer.
+const BUFFERED_ENTRIES: usize = 1024;
+
+/// Whether an entry is a directory or a file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    Dir,
+    File,
+}
+
+/// An entry of a planned tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedEntry {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+    /// The file's size in bytes, always zero for directories.
+    pub size: u64,
+    /// The seed from which the file's contents are generated, always `None`
+    /// for directories and empty files.
+    pub content_seed: Option<ContentSeed>,
+}
+
+/// Hands the entries planned by tasks to a consumer.
+///
+/// Tasks complete in an arbitrary order, so each one reserves a ticket when it
+/// is queued and waits for every earlier task to finish sending before sending
+/// its own entries. Entries are thus sent in the scheduler's order. Sending
+/// blocks while the consumer is behind, pausing the scheduler.
+///
+/// Waiting can't deadlock for the same reason it can't in
+/// [`Archive`](crate::core::Archive): tickets are reserved in the
+/// order tasks are spawned on the blocking pool, which starts them in that
+/// same order.
+#[derive(Debug)]
+pub struct Planner {
+    sender: SyncSender<PlannedEntry>,
+    /// The ticket of the task currently allowed to send.
+    next: Mutex<u64>,
+    /// Signalled whenever a task hands the planner over to the next ticket.
+    turn: Condvar,
+    tickets: AtomicU64,
+}
+
+impl Planner {
+    pub fn new() -> (Self, Receiver<PlannedEntry>) {
+        let (sender, receiver) = sync_channel(BUFFERED_ENTRIES);
+        (
+            Self {
+                sender,
+                next: Mutex::new(0),
+                turn: Condvar::new(),
+                tickets: AtomicU64::new(0),
+            },
+            receiver,
+        )
+    }
+
+    /// Reserves the position of the next task's entries.
+    pub fn reserve(&self) -> u64 {
+        self.tickets.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub const fn task(&self, ticket: u64) -> TaskPlanner<'_> {
+        TaskPlanner {
+            planner: self,
+            ticket,
+            turn: None,
+        }
+    }
+
+    /// Blocks until every task before `ticket` has sent its entries.
+    fn wait_for(&self, ticket: u64) -> MutexGuard<'_, u64> {
+        let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+        while *next != ticket {
+            next = self.turn.wait(next).unwrap_or_else(PoisonError::into_inner);
+        }
+        next
+    }
+}
+
+/// Sends the entries of a single task to the consumer.
+///
+/// The task waits for its turn when sending its first entry and hands the
+/// planner over to the next task once dropped.
+pub struct TaskPlanner<'a> {
+    planner: &'a Planner,
+    ticket: u64,
+    turn: Option<MutexGuard<'a, u64>>,
+}
+
+impl TaskPlanner<'_> {
+    pub fn dir(&mut self, path: &Path) -> io::Result<()> {
+        self.send(PlannedEntry {
+            path: path.to_path_buf(),
+            kind: EntryKind::Dir,
+            size: 0,
+            content_seed: None,
+        })
+    }
+
+    pub fn file(
+        &mut self,
+        path: &Path,
+        size: u64,
+        content_seed: Option<ContentSeed>,
+    ) -> io::Result<()> {
+        self.send(PlannedEntry {
+            path: path.to_path_buf(),
+            kind: EntryKind::File,
+            size,
+            content_seed,
+        })
+    }
+
+    fn send(&mut self, entry: PlannedEntry) -> io::Result<()> {
+        let Self {
+            planner,
+            ticket,
+            ref mut turn,
+        } = *self;
+        turn.get_or_insert_with(|| planner.wait_for(ticket));
+        planner.sender.send(entry).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "The planned entries are no longer being consumed",
+            )
+        })
+    }
+}
+
+impl Drop for TaskPlanner<'_> {
+    fn drop(&mut self) {
+        // Hand over the planner even if this task failed so later tasks aren't
+        // left waiting forever
+        let mut next = self
+            .turn
+            .take()
+            .unwrap_or_else(|| self.planner.wait_for(self.ticket));
+        *next += 1;
+        drop(next);
+        self.planner.turn.notify_all();
+    }
+}
+
+/// Discards a planned file's contents, keeping the seed they're generated from.
+pub struct SeedSink<'a>(pub &'a mut Option<ContentSeed>);
+
+impl ContentsSink for SeedSink<'_> {
+    fn open(&mut self, _: u64) -> io::Result<impl Write> {
+        Ok(io::sink())
+    }
+
+    fn seed(&mut self, seed: impl FnOnce() -> ContentSeed) {
+        *self.0 = Some(seed());
+    }
+}
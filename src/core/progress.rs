@@ -0,0 +1,263 @@
+use std::{
+    fmt::Write as _,
+    io,
+    io::{IsTerminal, Stderr, Write},
+    time::{Duration, Instant},
+};
+
+use bytesize::ByteSize;
+use log::{Level, log};
+use thousands::Separable;
+
+use crate::core::GeneratorStats;
+
+/// The width of the progress bar in characters.
+const BAR_WIDTH: usize = 24;
+
+/// Reports the progress of a run on stderr as tasks complete.
+///
+/// A progress bar is redrawn in place when stderr is a terminal while
+/// periodic log lines are emitted otherwise.
+#[derive(Debug)]
+pub struct Progress {
+    target: ProgressTarget,
+    start: Instant,
+    last_report: Option<Instant>,
+    terminal: Option<Stderr>,
+}
+
+/// The counts a run is expected to reach.
+#[derive(Debug, Copy, Clone)]
+pub struct ProgressTarget {
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+}
+
+impl Progress {
+    pub fn new(target: ProgressTarget) -> Self {
+        let stderr = io::stderr();
+        Self {
+            target,
+            start: Instant::now(),
+            last_report: None,
+            terminal: stderr.is_terminal().then_some(stderr),
+        }
+    }
+
+    /// Reports the accumulated stats if enough time has passed since the last
+    /// report.
+    pub fn update(&mut self, stats: &GeneratorStats) {
+        let interval = if self.terminal.is_some() {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_secs(5)
+        };
+        let now = Instant::now();
+        if now.duration_since(self.last_report.unwrap_or(self.start)) < interval {
+            return;
+        }
+        self.last_report = Some(now);
+
+        let line = self.describe(stats, now.duration_since(self.start));
+        if let Some(stderr) = &self.terminal {
+            // Progress is best effort
+            let _ = write!(stderr.lock(), "\r\x1b[K{line}");
+        } else {
+            log!(Level::Info, "{line}");
+        }
+    }
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    fn describe(&self, stats: &GeneratorStats, elapsed: Duration) -> String {
+        let Self {
+            target, terminal, ..
+        } = self;
+        let &GeneratorStats {
+            files, dirs, bytes, ..
+        } = stats;
+        let dirs = dirs as u64;
+
+        // Files vary in size, so bytes track the remaining work more closely
+        let fraction = if target.bytes > 0 {
+            bytes as f64 / target.bytes as f64
+        } else if target.files > 0 {
+            files as f64 / target.files as f64
+        } else if target.dirs > 0 {
+            dirs as f64 / target.dirs as f64
+        } else {
+            1.
+        }
+        .min(1.);
+        let secs = elapsed.as_secs_f64();
+
+        let mut line = String::new();
+        if terminal.is_some() {
+            let filled = (fraction * BAR_WIDTH as f64) as usize;
+            let _ = write!(
+                line,
+                "[{}{}] ",
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled)
+            );
+        }
+        let _ = write!(
+            line,
+            "{:.0}% {} files, {} dirs",
+            fraction * 100.,
+            files.separate_with_commas(),
+            dirs.separate_with_commas(),
+        );
+        if target.bytes > 0 {
+            let _ = write!(line, ", {}", ByteSize(bytes).display().si());
+        }
+        if secs > 0. {
+            let _ = write!(
+                line,
+                " | {} files/s",
+                ((files as f64 / secs) as u64).separate_with_commas()
+            );
+            if target.bytes > 0 {
+                let _ = write!(
+                    line,
+                    ", {}/s",
+                    ByteSize((bytes as f64 / secs) as u64).display().si()
+                );
+            }
+        }
+        // Barely started runs can extrapolate to more time than a duration holds
+        if fraction > 0.
+            && fraction < 1.
+            && let Ok(remaining) = Duration::try_from_secs_f64(secs / fraction - secs)
+        {
+            let _ = write!(line, " | ETA {}", format_duration(remaining));
+        }
+        line
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(stderr) = &self.terminal
+            && self.last_report.is_some()
+        {
+            let _ = write!(stderr.lock(), "\r\x1b[K");
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(target: ProgressTarget) -> Progress {
+        Progress {
+            target,
+            start: Instant::now(),
+            last_report: None,
+            terminal: None,
+        }
+    }
+
+    fn stats(files: u64, dirs: usize, bytes: u64) -> GeneratorStats {
+        GeneratorStats {
+            files,
+            dirs,
+            bytes,
+            reused_files: 0,
+            empty_files: 0,
+            digest: 0,
+            stopped_early: false,
+        }
+    }
+
+    #[test]
+    fn describe_reports_counts_rates_and_eta() {
+        let progress = progress(ProgressTarget {
+            files: 1_000,
+            dirs: 100,
+            bytes: 0,
+        });
+
+        assert_eq!(
+            progress.describe(&stats(250, 10, 0), Duration::from_secs(10)),
+            "25% 250 files, 10 dirs | 25 files/s | ETA 30s"
+        );
+        assert_eq!(
+            progress.describe(&stats(0, 0, 0), Duration::ZERO),
+            "0% 0 files, 0 dirs"
+        );
+        assert_eq!(
+            progress.describe(&stats(1_000, 100, 0), Duration::from_secs(4)),
+            "100% 1,000 files, 100 dirs | 250 files/s"
+        );
+    }
+
+    #[test]
+    fn dirs_only_progress_is_measured_in_dirs() {
+        let progress = progress(ProgressTarget {
+            files: 0,
+            dirs: 100,
+            bytes: 0,
+        });
+
+        assert_eq!(
+            progress.describe(&stats(0, 50, 0), Duration::from_secs(60)),
+            "50% 0 files, 50 dirs | 0 files/s | ETA 1m 00s"
+        );
+    }
+
+    #[test]
+    fn eta_is_weighted_by_bytes() {
+        let progress = progress(ProgressTarget {
+            files: 1_000,
+            dirs: 100,
+            bytes: 1_000_000,
+        });
+
+        // Half the files but only a quarter of the bytes have been written
+        assert_eq!(
+            progress.describe(&stats(500, 50, 250_000), Duration::from_secs(10)),
+            "25% 500 files, 50 dirs, 250.0 kB | 50 files/s, 25.0 kB/s | ETA 30s"
+        );
+    }
+
+    #[test]
+    fn unrepresentable_eta_is_omitted() {
+        let progress = progress(ProgressTarget {
+            files: 1,
+            dirs: 0,
+            bytes: u64::MAX,
+        });
+
+        assert_eq!(
+            progress.describe(&stats(0, 0, 1), Duration::from_secs(10)),
+            "0% 0 files, 0 dirs, 1 B | 0 files/s, 0 B/s"
+        );
+    }
+
+    #[test]
+    fn durations_are_formatted_with_two_units() {
+        assert_eq!(format_duration(Duration::from_millis(59_999)), "59s");
+        assert_eq!(format_duration(Duration::from_secs(61)), "1m 01s");
+        assert_eq!(format_duration(Duration::from_secs(3_599)), "59m 59s");
+        assert_eq!(format_duration(Duration::from_secs(3_661)), "1h 01m");
+        assert_eq!(
+            format_duration(Duration::from_secs(100 * 3_600)),
+            "100h 00m"
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use std::{
-    cmp::max,
+    cmp::{max, min},
     collections::VecDeque,
     io, mem,
-    num::{NonZeroU64, NonZeroUsize},
-    ops::AddAssign,
+    num::NonZeroUsize,
+    ops::{AddAssign, ControlFlow},
     path::PathBuf,
     process::ExitCode,
     result,
@@ -15,7 +15,9 @@ use tokio::task::{JoinError, JoinHandle};
 
 use crate::{
     core::{
+        cancel::StopCondition,
         files::GeneratorTaskOutcome,
+        progress::Progress,
         tasks::{QueueErrors, QueueOutcome, TaskGenerator},
         truncatable_normal,
     },
@@ -28,6 +30,14 @@ pub struct GeneratorStats {
     pub files: u64,
     pub dirs: usize,
     pub bytes: u64,
+    /// The number of pre-existing files which were left untouched when
+    /// resuming.
+    pub reused_files: u64,
+    pub empty_files: u64,
+    /// The wrapping sum of the digests of all entries.
+    pub digest: u64,
+    /// Whether the run was stopped before all of its tasks were queued.
+    pub stopped_early: bool,
 }
 
 impl AddAssign<&GeneratorTaskOutcome> for GeneratorStats {
@@ -37,21 +47,28 @@ impl AddAssign<&GeneratorTaskOutcome> for GeneratorStats {
             files_generated,
             dirs_generated,
             bytes_generated,
+            files_reused,
+            files_empty,
+            digest,
             ..
         }: &GeneratorTaskOutcome,
     ) {
         self.files += files_generated;
         self.dirs += dirs_generated;
         self.bytes += bytes_generated;
+        self.reused_files += files_reused;
+        self.empty_files += files_empty;
+        self.digest = self.digest.wrapping_add(*digest);
     }
 }
 
 struct Scheduler<'a> {
-    #[cfg(not(feature = "dry_run"))]
     tasks: &'a mut VecDeque<JoinHandle<Result<GeneratorTaskOutcome, io::Error>>>,
-    #[cfg(feature = "dry_run")]
-    tasks: &'a mut VecDeque<GeneratorTaskOutcome>,
     stats: &'a mut GeneratorStats,
+    progress: Option<&'a mut Progress>,
+    stop: &'a StopCondition,
+    /// The maximum number of in-flight tasks.
+    queue_depth: usize,
 
     stack: Vec<Directory>,
     target_dir: FastPathBuf,
@@ -83,24 +100,35 @@ struct ObjectPool {
     byte_counts: Vec<Vec<u64>>,
 }
 
+/// Generates a tree of directories rooted at `root_dir`.
+///
+/// Every random decision is made here in queue order while `queue_depth` only
+/// bounds the number of in-flight tasks, so the generated tree never depends
+/// on it.
 #[cfg_attr(
     feature = "tracing",
     tracing::instrument(level = "trace", skip(generator))
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     root_dir: PathBuf,
-    target_file_count: NonZeroU64,
+    target_file_count: u64,
     dirs_per_dir: f64,
     max_depth: usize,
-    parallelism: NonZeroUsize,
+    queue_depth: NonZeroUsize,
     mut generator: impl TaskGenerator + Send,
+    mut progress: Option<Progress>,
+    stop: &StopCondition,
 ) -> Result<GeneratorStats, Error> {
-    // Minus 1 because VecDeque adds 1 and then rounds to a power of 2
-    let mut tasks = VecDeque::with_capacity(parallelism.get().pow(2) - 1);
+    let mut tasks = VecDeque::with_capacity(queue_depth.get());
     let mut stats = GeneratorStats {
         files: 0,
         dirs: 0,
         bytes: 0,
+        reused_files: 0,
+        empty_files: 0,
+        digest: 0,
+        stopped_early: false,
     };
 
     let mut scheduler = Scheduler {
@@ -122,6 +150,9 @@ pub async fn run(
 
         tasks: &mut tasks,
         stats: &mut stats,
+        progress: progress.as_mut(),
+        stop,
+        queue_depth: queue_depth.get(),
     };
 
     #[cfg(feature = "tracing")]
@@ -149,6 +180,11 @@ pub async fn run(
         ref mut child_dir_counts,
     }) = scheduler.stack.last_mut()
     {
+        if scheduler.stop.should_stop() {
+            scheduler.stats.stopped_early = true;
+            break;
+        }
+
         let Some(DirChild {
             files: target_file_count,
             dirs: num_dirs_to_generate,
@@ -161,11 +197,7 @@ pub async fn run(
         let next_stack_dir = total_dirs - child_dir_counts.len();
         let is_completing = child_dir_counts.is_empty();
 
-        if scheduler.tasks.len() + num_dirs_to_generate >= scheduler.tasks.capacity() {
-            flush_tasks(&mut scheduler).await?;
-        }
-
-        let Ok(directory) = schedule_task(
+        let ControlFlow::Continue(directory) = schedule_task(
             target_file_count,
             num_dirs_to_generate,
             dirs_per_dir,
@@ -174,7 +206,9 @@ pub async fn run(
             &mut scheduler,
             #[cfg(feature = "tracing")]
             &gen_span,
-        ) else {
+        )
+        .await?
+        else {
             break;
         };
 
@@ -190,26 +224,139 @@ pub async fn run(
     #[cfg(feature = "tracing")]
     drop(gen_span);
 
-    schedule_last_task(generator, scheduler);
+    if !scheduler.stats.stopped_early {
+        if scheduler.tasks.len() >= scheduler.queue_depth {
+            flush_tasks(&mut scheduler).await?;
+        }
+        schedule_last_task(generator, scheduler);
+    }
+    await_remaining_tasks(tasks, stats, progress).await
+}
+
+/// The maximum number of files a single task will create when generating a
+/// flat directory.
+///
+/// Note that this value must not depend on the number of jobs or the queue
+/// depth as each chunk receives its own seed.
+const FLAT_CHUNK_SIZE: u64 = 1 << 14;
 
+/// Generates all files in the root directory, splitting their creation into
+/// tasks of at most [`FLAT_CHUNK_SIZE`] files.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(generator))
+)]
+pub async fn run_flat(
+    root_dir: PathBuf,
+    target_file_count: u64,
+    queue_depth: NonZeroUsize,
+    mut generator: impl TaskGenerator + Send,
+    mut progress: Option<Progress>,
+    stop: &StopCondition,
+) -> Result<GeneratorStats, Error> {
+    let mut tasks = VecDeque::with_capacity(queue_depth.get());
+    let mut stats = GeneratorStats {
+        files: 0,
+        dirs: 0,
+        bytes: 0,
+        reused_files: 0,
+        empty_files: 0,
+        digest: 0,
+        stopped_early: false,
+    };
+
+    let mut scheduler = Scheduler {
+        stack: Vec::new(),
+        target_dir: FastPathBuf::from(root_dir),
+
+        cache: ObjectPool {
+            directories: Vec::new(),
+            byte_counts: if generator.uses_byte_counts_pool() {
+                Vec::with_capacity(tasks.capacity() / 2)
+            } else {
+                Vec::default()
+            },
+            paths: Vec::with_capacity(tasks.capacity() / 2),
+        },
+
+        tasks: &mut tasks,
+        stats: &mut stats,
+        progress: progress.as_mut(),
+        stop,
+        queue_depth: queue_depth.get(),
+    };
+
+    let num_files = generator.num_flat_files(&num_files_distr(target_file_count, 0., 0));
+    let mut offset = 0;
+    while offset < num_files {
+        // Waiting for room may take a while, so only check whether to stop
+        // afterwards
+        if scheduler.tasks.len() >= scheduler.queue_depth {
+            flush_tasks(&mut scheduler).await?;
+        }
+        if scheduler.stop.should_stop() {
+            scheduler.stats.stopped_early = true;
+            break;
+        }
+        let chunk = min(FLAT_CHUNK_SIZE, num_files - offset);
+
+        let Scheduler {
+            ref mut tasks,
+            ref target_dir,
+            cache:
+                ObjectPool {
+                    paths: ref mut path_pool,
+                    byte_counts: ref mut byte_counts_pool,
+                    ..
+                },
+            ..
+        } = scheduler;
+
+        let mut path = path_pool.pop().unwrap_or_else(FastPathBuf::new);
+        path.clone_from(target_dir);
+        match generator.queue_flat_gen(
+            path,
+            chunk,
+            offset,
+            offset + chunk == num_files,
+            byte_counts_pool,
+        ) {
+            Ok(QueueOutcome { task, .. }) => tasks.push_back(task),
+            Err(QueueErrors::NothingToDo(path)) => path_pool.push(path),
+        }
+
+        offset += chunk;
+    }
+
+    if !scheduler.stats.stopped_early {
+        if scheduler.tasks.len() >= scheduler.queue_depth {
+            flush_tasks(&mut scheduler).await?;
+        }
+        schedule_last_task(generator, scheduler);
+    }
+    await_remaining_tasks(tasks, stats, progress).await
+}
+
+async fn await_remaining_tasks(
+    tasks: VecDeque<JoinHandle<Result<GeneratorTaskOutcome, io::Error>>>,
+    mut stats: GeneratorStats,
+    mut progress: Option<Progress>,
+) -> Result<GeneratorStats, Error> {
     for task in tasks {
-        #[cfg(not(feature = "dry_run"))]
         handle_task_result(task.await, &mut stats)?;
-        #[cfg(feature = "dry_run")]
-        handle_task_result(task, &mut stats)?;
+        if let Some(progress) = &mut progress {
+            progress.update(&stats);
+        }
     }
 
     Ok(stats)
 }
 
-#[cfg_attr(
-    feature = "tracing",
-    tracing::instrument(level = "trace", skip(tasks, path_pool, byte_counts_pool))
-)]
 async fn flush_tasks(
     &mut Scheduler {
         ref mut tasks,
         ref mut stats,
+        ref mut progress,
         cache:
             ObjectPool {
                 directories: _,
@@ -218,11 +365,31 @@ async fn flush_tasks(
             },
         ..
     }: &mut Scheduler<'_>,
+) -> Result<(), Error> {
+    drain_tasks(
+        tasks,
+        stats,
+        progress.as_deref_mut(),
+        path_pool,
+        byte_counts_pool,
+    )
+    .await
+}
+
+/// Waits for at least half of the queued tasks, preferring those which have
+/// already finished, to make room for new ones.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+async fn drain_tasks(
+    tasks: &mut VecDeque<JoinHandle<Result<GeneratorTaskOutcome, io::Error>>>,
+    stats: &mut GeneratorStats,
+    progress: Option<&mut Progress>,
+    path_pool: &mut Vec<FastPathBuf>,
+    byte_counts_pool: &mut Vec<Vec<u64>>,
 ) -> Result<(), Error> {
     #[cfg(feature = "tracing")]
     tracing::event!(tracing::Level::TRACE, "Flushing pending task queue");
 
-    let mut drain = tasks.len() / 2;
+    let mut drain = max(1, tasks.len() / 2);
     while drain > 0 {
         let task = {
             let mut task = tasks.pop_front().unwrap();
@@ -234,10 +401,7 @@ async fn flush_tasks(
             task
         };
 
-        #[cfg(not(feature = "dry_run"))]
         let outcome = handle_task_result(task.await, stats)?;
-        #[cfg(feature = "dry_run")]
-        let outcome = handle_task_result(task, stats)?;
 
         path_pool.push(outcome.pool_return_file);
         if let Some(mut vec) = outcome.pool_return_byte_counts {
@@ -247,18 +411,17 @@ async fn flush_tasks(
 
         drain -= 1;
     }
+
+    if let Some(progress) = progress {
+        progress.update(stats);
+    }
     Ok(())
 }
 
 fn handle_task_result(
-    #[cfg(not(feature = "dry_run"))] task_result: result::Result<
-        Result<GeneratorTaskOutcome, io::Error>,
-        JoinError,
-    >,
-    #[cfg(feature = "dry_run")] outcome: GeneratorTaskOutcome,
+    task_result: result::Result<Result<GeneratorTaskOutcome, io::Error>, JoinError>,
     stats: &mut GeneratorStats,
 ) -> Result<GeneratorTaskOutcome, Error> {
-    #[cfg(not(feature = "dry_run"))]
     let outcome = task_result
         .change_context(Error::TaskJoin)
         .attach(ExitCode::from(sysexits::ExitCode::Software))?
@@ -277,12 +440,15 @@ fn handle_task_result(
 )]
 fn schedule_root_dir(
     generator: &mut impl TaskGenerator,
-    target_file_count: NonZeroU64,
+    target_file_count: u64,
     dirs_per_dir: f64,
     max_depth: usize,
     &mut Scheduler {
         ref mut tasks,
         stats: _,
+        progress: _,
+        stop: _,
+        queue_depth: _,
         ref mut stack,
         ref target_dir,
         cache:
@@ -294,7 +460,7 @@ fn schedule_root_dir(
     }: &mut Scheduler<'_>,
 ) {
     match generator.queue_gen(
-        &num_files_distr(target_file_count.get(), dirs_per_dir, max_depth),
+        &num_files_distr(target_file_count, dirs_per_dir, max_depth),
         target_dir.clone(),
         max_depth > 0,
         byte_counts_pool,
@@ -310,7 +476,7 @@ fn schedule_root_dir(
                 stack.push(Directory {
                     total_dirs: 1,
                     child_dir_counts: vec![DirChild {
-                        files: next_target_file_count(target_file_count.get(), num_dirs, num_files),
+                        files: next_target_file_count(target_file_count, num_dirs, num_files),
                         dirs: num_dirs,
                     }],
                 });
@@ -327,7 +493,7 @@ fn schedule_root_dir(
         skip(generator, tasks, stack, dir_pool, path_pool, byte_counts_pool)
     )
 )]
-fn schedule_task(
+async fn schedule_task(
     target_file_count: u64,
     num_dirs_to_generate: usize,
     dirs_per_dir: f64,
@@ -335,7 +501,10 @@ fn schedule_task(
     generator: &mut impl TaskGenerator,
     &mut Scheduler {
         ref mut tasks,
-        stats: _,
+        ref mut stats,
+        ref mut progress,
+        stop: _,
+        queue_depth,
         ref stack,
         ref target_dir,
         cache:
@@ -346,7 +515,7 @@ fn schedule_task(
             },
     }: &mut Scheduler<'_>,
     #[cfg(feature = "tracing")] gen_span: &tracing::Span,
-) -> result::Result<Option<Directory>, ()> {
+) -> Result<ControlFlow<(), Option<Directory>>, Error> {
     let depth = stack.len();
     let gen_next_dirs = depth < max_depth;
 
@@ -370,9 +539,25 @@ fn schedule_task(
         with_file_name(num_files_distr.mean().round() as u64, str::len),
     );
 
-    #[cfg(feature = "tracing")]
-    let span_guard = gen_span.enter();
     for i in 0..num_dirs_to_generate {
+        // Each child gets its own task, so the queue may fill up mid-directory
+        if tasks.len() >= queue_depth {
+            drain_tasks(
+                tasks,
+                stats,
+                progress.as_deref_mut(),
+                path_pool,
+                byte_counts_pool,
+            )
+            .await?;
+        }
+        #[cfg(feature = "tracing")]
+        let _span_guard = gen_span.enter();
+
+        // Only leaf directories may be left empty so that entire subtrees don't go
+        // missing
+        let empty = !gen_next_dirs && generator.gen_empty_dir();
+
         let path = with_dir_name(i, |s| {
             let mut buf = path_pool.pop().unwrap_or_else(FastPathBuf::new);
 
@@ -388,6 +573,11 @@ fn schedule_task(
 
             buf
         });
+        if empty {
+            generator.leave_empty(&path);
+            path_pool.push(path);
+            continue;
+        }
 
         let child =
             match generator.queue_gen(&num_files_distr, path, gen_next_dirs, byte_counts_pool) {
@@ -399,7 +589,7 @@ fn schedule_task(
                 }) => {
                     tasks.push_back(task);
                     if done {
-                        return Err(());
+                        return Ok(ControlFlow::Break(()));
                     }
                     DirChild {
                         files: next_target_file_count(target_file_count, num_dirs, num_files),
@@ -407,6 +597,7 @@ fn schedule_task(
                     }
                 }
                 Err(QueueErrors::NothingToDo(path)) => {
+                    generator.leave_empty(&path);
                     path_pool.push(path);
                     DirChild { files: 0, dirs: 0 }
                 }
@@ -416,20 +607,18 @@ fn schedule_task(
             raw_next_dirs[num_dirs_to_generate - i - 1].write(child);
         }
     }
-    #[cfg(feature = "tracing")]
-    drop(span_guard);
 
     if gen_next_dirs {
         unsafe {
             next_dirs.set_len(num_dirs_to_generate);
         }
-        Ok(Some(Directory {
+        Ok(ControlFlow::Continue(Some(Directory {
             total_dirs: num_dirs_to_generate,
             child_dir_counts: next_dirs,
-        }))
+        })))
     } else {
         dir_pool.push(next_dirs);
-        Ok(None)
+        Ok(ControlFlow::Continue(None))
     }
 }
 
@@ -438,6 +627,9 @@ fn schedule_last_task(mut generator: impl TaskGenerator, mut scheduler: Schedule
     let Scheduler {
         ref mut tasks,
         stats: _,
+        progress: _,
+        stop: _,
+        queue_depth: _,
         stack: _,
         target_dir,
         cache:
@@ -466,6 +658,9 @@ fn handle_directory_completion(
     &mut Scheduler {
         tasks: _,
         stats: _,
+        progress: _,
+        stop: _,
+        queue_depth: _,
         ref mut stack,
         ref mut target_dir,
         cache:
@@ -1,18 +1,26 @@
 #![allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
 
-use std::{cmp::min, io, num::NonZeroU64};
+use std::{cmp::min, io, num::NonZeroU64, sync::Arc};
 
-use rand::RngCore;
-use rand_distr::Normal;
+use rand::{RngCore, distr::Bernoulli};
+use rand_distr::{Distribution, Normal};
 use tokio::{task, task::JoinHandle};
 
 use crate::{
     core::{
+        digest::TreeDigest,
+        durability::Syncer,
         file_contents::{
             FileContentsGenerator, NoGeneratedFileContents, OnTheFlyGeneratedFileContents,
             PreDefinedGeneratedFileContents,
         },
-        files::{GeneratorTaskOutcome, GeneratorTaskParams, create_files_and_dirs},
+        files::{
+            GeneratorTaskOutcome, GeneratorTaskParams, TaskMode, create_files_and_dirs,
+            skip_files_and_dirs,
+        },
+        journal::Journal,
+        manifest::Manifest,
+        observer::Observer,
         sample_truncated,
     },
     utils::FastPathBuf,
@@ -21,10 +29,7 @@ use crate::{
 pub type QueueResult = Result<QueueOutcome, QueueErrors>;
 
 pub struct QueueOutcome {
-    #[cfg(not(feature = "dry_run"))]
     pub task: JoinHandle<error_stack::Result<GeneratorTaskOutcome, io::Error>>,
-    #[cfg(feature = "dry_run")]
-    pub task: GeneratorTaskOutcome,
 
     pub num_files: u64,
     pub num_dirs: usize,
@@ -45,6 +50,29 @@ pub trait TaskGenerator {
         byte_counts_pool: &mut Vec<Vec<u64>>,
     ) -> QueueResult;
 
+    /// Determines the number of files to generate in a flat directory whose
+    /// creation will be split into chunks via [`Self::queue_flat_gen`].
+    fn num_flat_files(&mut self, num_files_distr: &Normal<f64>) -> u64;
+
+    fn queue_flat_gen(
+        &mut self,
+        file: FastPathBuf,
+        num_files: u64,
+        offset: u64,
+        done: bool,
+        byte_counts_pool: &mut Vec<Vec<u64>>,
+    ) -> QueueResult;
+
+    /// Decides whether the next leaf directory should be left empty instead of
+    /// being populated with files.
+    fn gen_empty_dir(&mut self) -> bool {
+        false
+    }
+
+    /// Called with every directory other than the root which is left without
+    /// any entries.
+    fn leave_empty(&mut self, _dir: &FastPathBuf) {}
+
     fn maybe_queue_final_gen(&mut self, file: FastPathBuf, _: &mut Vec<Vec<u64>>) -> QueueResult {
         Err(QueueErrors::NothingToDo(file))
     }
@@ -59,7 +87,7 @@ pub trait TaskGenerator {
     tracing::instrument(level = "trace", skip(params))
 )]
 fn queue(
-    params @ GeneratorTaskParams {
+    mut params @ GeneratorTaskParams {
         num_files,
         num_dirs,
         ..
@@ -67,25 +95,38 @@ fn queue(
     done: bool,
 ) -> QueueResult {
     if num_files > 0 || num_dirs > 0 {
+        if let TaskMode::Archive { archive, ticket } = &mut params.mode {
+            // Tasks are queued in traversal order, so reserving their place in the archive
+            // here keeps the archive deterministic
+            *ticket = archive.reserve();
+        }
+        if let TaskMode::Plan { planner, ticket } = &mut params.mode {
+            // Likewise for the order in which planned entries are handed over
+            *ticket = planner.reserve();
+        }
+        if let Some(manifest) = &params.manifest {
+            // As are the manifest's records
+            params.manifest_ticket = manifest.reserve();
+        }
+        if let Some(journal) = &params.journal {
+            params.task = journal.reserve();
+        }
+        let task = if params
+            .journal
+            .as_ref()
+            .is_some_and(|journal| journal.is_complete(params.task))
+        {
+            let outcome = skip_files_and_dirs(params);
+            task::spawn(async move { Ok(outcome) })
+        } else {
+            task::spawn_blocking(move || create_files_and_dirs(params))
+        };
         Ok(QueueOutcome {
+            task,
+
             num_files,
             num_dirs,
             done,
-
-            #[cfg(not(feature = "dry_run"))]
-            task: task::spawn_blocking(move || create_files_and_dirs(params)),
-            #[cfg(feature = "dry_run")]
-            task: {
-                std::hint::black_box(&params);
-                GeneratorTaskOutcome {
-                    files_generated: num_files,
-                    dirs_generated: num_dirs,
-                    bytes_generated: 0,
-
-                    pool_return_file: params.target_dir,
-                    pool_return_byte_counts: None,
-                }
-            },
         })
     } else {
         Err(QueueErrors::NothingToDo(params.target_dir))
@@ -118,6 +159,13 @@ pub struct GeneratorBytes {
 pub struct DynamicGenerator<R> {
     pub num_dirs_distr: Normal<f64>,
     pub random: R,
+    pub empty_dirs: Option<Bernoulli>,
+    pub mode: TaskMode,
+    pub manifest: Option<Arc<Manifest>>,
+    pub digest: Option<Arc<TreeDigest>>,
+    pub observer: Option<Arc<dyn Observer>>,
+    pub sync: Option<Arc<Syncer>>,
+    pub journal: Option<Arc<Journal>>,
 
     pub bytes: Option<GeneratorBytes>,
 }
@@ -134,19 +182,91 @@ impl<R: RngCore + Clone + Send + 'static> TaskGenerator for DynamicGenerator<R>
         let Self {
             ref num_dirs_distr,
             ref mut random,
-            ref bytes,
+            empty_dirs: _,
+            mode: _,
+            manifest: _,
+            digest: _,
+            observer: _,
+            sync: _,
+            journal: _,
+            bytes: _,
         } = *self;
 
         let num_files = sample_truncated(num_files_distr, random);
         let num_dirs = dirs_to_gen(num_files, gen_dirs, num_dirs_distr, random);
 
+        self.queue_gen_internal(file, num_files, num_dirs, 0)
+    }
+
+    fn gen_empty_dir(&mut self) -> bool {
+        let Self {
+            ref mut random,
+            ref empty_dirs,
+            ..
+        } = *self;
+
+        empty_dirs.is_some_and(|distr| distr.sample(random))
+    }
+
+    fn leave_empty(&mut self, dir: &FastPathBuf) {
+        if let TaskMode::Verify(verifier) = &self.mode {
+            verifier.empty_dir(dir);
+        }
+    }
+
+    fn num_flat_files(&mut self, num_files_distr: &Normal<f64>) -> u64 {
+        sample_truncated(num_files_distr, &mut self.random)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(self)))]
+    fn queue_flat_gen(
+        &mut self,
+        file: FastPathBuf,
+        num_files: u64,
+        offset: u64,
+        _: bool,
+        _: &mut Vec<Vec<u64>>,
+    ) -> QueueResult {
+        self.queue_gen_internal(file, num_files, 0, offset)
+    }
+}
+
+impl<R: RngCore + Clone + Send + 'static> DynamicGenerator<R> {
+    fn queue_gen_internal(
+        &mut self,
+        file: FastPathBuf,
+        num_files: u64,
+        num_dirs: usize,
+        offset: u64,
+    ) -> QueueResult {
+        let Self {
+            num_dirs_distr: _,
+            ref mut random,
+            empty_dirs: _,
+            ref mode,
+            ref manifest,
+            ref digest,
+            ref observer,
+            ref sync,
+            ref journal,
+            ref bytes,
+        } = *self;
+
         macro_rules! build_params {
             ($file_contents:expr) => {{
                 GeneratorTaskParams {
                     target_dir: file,
                     num_files,
                     num_dirs,
-                    file_offset: 0,
+                    file_offset: offset,
+                    mode: mode.clone(),
+                    manifest: manifest.clone(),
+                    digest: digest.clone(),
+                    observer: observer.clone(),
+                    sync: sync.clone(),
+                    journal: journal.clone(),
+                    task: 0,
+                    manifest_ticket: 0,
                     file_contents: $file_contents,
                 }
             }};
@@ -162,6 +282,9 @@ impl<R: RngCore + Clone + Send + 'static> TaskGenerator for DynamicGenerator<R>
                     num_bytes_distr,
                     seed: random.next_u64(),
                     fill_byte,
+                    hash: manifest.is_some()
+                        || digest.is_some()
+                        || matches!(mode, TaskMode::Verify(_)),
                 }),
                 false,
             )
@@ -198,6 +321,13 @@ impl<R: RngCore + Clone + Send + 'static> TaskGenerator for StaticGenerator<R> {
                 DynamicGenerator {
                     ref num_dirs_distr,
                     ref mut random,
+                    empty_dirs: _,
+                    mode: _,
+                    manifest: _,
+                    digest: _,
+                    observer: _,
+                    sync: _,
+                    journal: _,
                     bytes: _,
                 },
             ref mut files_exact,
@@ -230,6 +360,48 @@ impl<R: RngCore + Clone + Send + 'static> TaskGenerator for StaticGenerator<R> {
         self.queue_gen_internal(file, num_files, num_dirs, 0, byte_counts_pool)
     }
 
+    fn num_flat_files(&mut self, num_files_distr: &Normal<f64>) -> u64 {
+        let Self {
+            dynamic: DynamicGenerator { ref mut random, .. },
+            ref mut files_exact,
+            bytes_exact: _,
+            done: _,
+            ref mut root_num_files_hack,
+        } = *self;
+
+        let num_files = files_exact
+            .take()
+            .unwrap_or_else(|| sample_truncated(num_files_distr, random));
+        *root_num_files_hack = Some(num_files);
+        num_files
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, byte_counts_pool))
+    )]
+    fn queue_flat_gen(
+        &mut self,
+        file: FastPathBuf,
+        num_files: u64,
+        offset: u64,
+        done: bool,
+        byte_counts_pool: &mut Vec<Vec<u64>>,
+    ) -> QueueResult {
+        debug_assert!(!self.done);
+
+        self.done = done;
+        self.queue_gen_internal(file, num_files, 0, offset, byte_counts_pool)
+    }
+
+    fn gen_empty_dir(&mut self) -> bool {
+        self.dynamic.gen_empty_dir()
+    }
+
+    fn leave_empty(&mut self, dir: &FastPathBuf) {
+        self.dynamic.leave_empty(dir);
+    }
+
     fn maybe_queue_final_gen(
         &mut self,
         file: FastPathBuf,
@@ -315,23 +487,18 @@ impl<R: RngCore + Clone + Send + 'static> StaticGenerator<R> {
         offset: u64,
         byte_counts_pool: &mut Vec<Vec<u64>>,
     ) -> QueueResult {
-        macro_rules! build_params {
-            ($file_contents:expr) => {{
-                GeneratorTaskParams {
-                    target_dir: file,
-                    num_files,
-                    num_dirs,
-                    file_offset: offset,
-                    file_contents: $file_contents,
-                }
-            }};
-        }
-
         let Self {
             dynamic:
                 DynamicGenerator {
                     num_dirs_distr: _,
                     ref mut random,
+                    empty_dirs: _,
+                    ref mode,
+                    ref manifest,
+                    ref digest,
+                    ref observer,
+                    ref sync,
+                    ref journal,
                     ref bytes,
                 },
             files_exact: _,
@@ -340,6 +507,26 @@ impl<R: RngCore + Clone + Send + 'static> StaticGenerator<R> {
             root_num_files_hack: _,
         } = *self;
 
+        macro_rules! build_params {
+            ($file_contents:expr) => {{
+                GeneratorTaskParams {
+                    target_dir: file,
+                    num_files,
+                    num_dirs,
+                    file_offset: offset,
+                    mode: mode.clone(),
+                    manifest: manifest.clone(),
+                    digest: digest.clone(),
+                    observer: observer.clone(),
+                    sync: sync.clone(),
+                    journal: journal.clone(),
+                    task: 0,
+                    manifest_ticket: 0,
+                    file_contents: $file_contents,
+                }
+            }};
+        }
+
         if num_files > 0
             && let Some(GeneratorBytes {
                 num_bytes_distr,
@@ -386,6 +573,9 @@ impl<R: RngCore + Clone + Send + 'static> StaticGenerator<R> {
                             byte_counts,
                             seed: random.next_u64(),
                             fill_byte,
+                            hash: manifest.is_some()
+                                || digest.is_some()
+                                || matches!(mode, TaskMode::Verify(_)),
                         }),
                         done,
                     )
@@ -398,6 +588,9 @@ impl<R: RngCore + Clone + Send + 'static> StaticGenerator<R> {
                         num_bytes_distr,
                         seed: random.next_u64(),
                         fill_byte,
+                        hash: manifest.is_some()
+                            || digest.is_some()
+                            || matches!(mode, TaskMode::Verify(_)),
                     }),
                     done,
                 )
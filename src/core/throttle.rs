@@ -0,0 +1,172 @@
+use std::{
+    cmp::max,
+    fmt::Debug,
+    io,
+    num::NonZeroU64,
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    core::{
+        backend::{Backend, EntryMetadata},
+        durability::Syncer,
+        file_contents::FileContents,
+    },
+    utils::FastPathBuf,
+};
+
+/// Limits the rate at which files and bytes are written, shared between every
+/// blocking worker.
+///
+/// Each file reserves the next free slot of both limits and waits for it
+/// before being written, so idle time isn't saved up for later bursts.
+#[derive(Debug)]
+pub struct Throttle {
+    files: Option<Limit>,
+    bytes: Option<Limit>,
+    written_files: AtomicU64,
+    written_bytes: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Limit {
+    per_sec: NonZeroU64,
+    next: Mutex<Option<Instant>>,
+}
+
+impl Limit {
+    const fn new(per_sec: NonZeroU64) -> Self {
+        Self {
+            per_sec,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Reserves `amount` units, returning the time at which they may be used.
+    #[allow(clippy::cast_precision_loss)]
+    fn reserve(&self, amount: u64, now: Instant) -> Instant {
+        let cost = Duration::from_secs_f64(amount as f64 / self.per_sec.get() as f64);
+        let mut next = self.next.lock().unwrap();
+        let start = next.map_or(now, |next| max(next, now));
+        *next = Some(start + cost);
+        drop(next);
+        start
+    }
+}
+
+/// The rates achieved by a throttled run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rates {
+    pub files_per_sec: u64,
+    pub bytes_per_sec: u64,
+}
+
+impl Throttle {
+    pub fn new(files_per_sec: Option<NonZeroU64>, bytes_per_sec: Option<NonZeroU64>) -> Self {
+        Self {
+            files: files_per_sec.map(Limit::new),
+            bytes: bytes_per_sec.map(Limit::new),
+            written_files: AtomicU64::new(0),
+            written_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Blocks until a file of `len` bytes may be written.
+    fn wait(&self, len: u64) {
+        let now = Instant::now();
+        let start = max(
+            self.files.as_ref().map(|limit| limit.reserve(1, now)),
+            self.bytes.as_ref().map(|limit| limit.reserve(len, now)),
+        );
+        if let Some(delay) = start.map(|start| start.saturating_duration_since(now)) {
+            thread::sleep(delay);
+        }
+    }
+
+    fn record(&self, len: u64) {
+        self.written_files.fetch_add(1, Ordering::Relaxed);
+        self.written_bytes.fetch_add(len, Ordering::Relaxed);
+    }
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn rates(&self, elapsed: Duration) -> Rates {
+        let secs = elapsed.as_secs_f64();
+        let rate = |count: &AtomicU64| {
+            let count = count.load(Ordering::Relaxed) as f64;
+            if secs > 0. {
+                (count / secs).round() as u64
+            } else {
+                0
+            }
+        };
+        Rates {
+            files_per_sec: rate(&self.written_files),
+            bytes_per_sec: rate(&self.written_bytes),
+        }
+    }
+}
+
+/// Writes files through `backend` no faster than the throttle allows.
+#[derive(Debug)]
+pub struct Throttled {
+    pub backend: Arc<dyn Backend>,
+    pub throttle: Arc<Throttle>,
+}
+
+impl Backend for Throttled {
+    fn create_dir(&self, path: &Path, metadata: &EntryMetadata) -> io::Result<()> {
+        self.backend.create_dir(path, metadata)
+    }
+
+    fn create_file(
+        &self,
+        path: &Path,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+    ) -> io::Result<()> {
+        self.throttle.wait(metadata.len);
+        self.backend.create_file(path, metadata, contents)?;
+        self.throttle.record(metadata.len);
+        Ok(())
+    }
+
+    fn file_metadata(&self, path: &Path) -> Option<EntryMetadata> {
+        self.backend.file_metadata(path)
+    }
+
+    fn create_file_in_place(
+        &self,
+        path: &mut FastPathBuf,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+        sync: Option<&Syncer>,
+    ) -> io::Result<()> {
+        self.throttle.wait(metadata.len);
+        self.backend
+            .create_file_in_place(path, metadata, contents, sync)?;
+        self.throttle.record(metadata.len);
+        Ok(())
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        self.backend.sync_file(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        self.backend.sync_dir(path)
+    }
+
+    fn sync_all(&self, root_dir: &Path) -> io::Result<()> {
+        self.backend.sync_all(root_dir)
+    }
+}
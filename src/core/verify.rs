@@ -0,0 +1,226 @@
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::{File, symlink_metadata},
+    io,
+    io::ErrorKind::{NotADirectory, NotFound},
+    mem,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    core::file_contents::{ContentHasher, FileOutcome},
+    utils::{with_dir_name, with_file_name},
+};
+
+/// A way in which an on-disk entry differs from the expected tree.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Discrepancy {
+    Missing,
+    Extra,
+    Resized,
+    ContentMismatch,
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Missing => "missing",
+            Self::Extra => "extra",
+            Self::Resized => "resized",
+            Self::ContentMismatch => "content mismatch",
+        })
+    }
+}
+
+/// Compares the tree on disk against the expected tree.
+///
+/// Each task looks for extra entries in its directory once it has verified the
+/// entries it expects there. The root directory may be populated by several
+/// tasks, so it is only checked once all of them have finished.
+#[derive(Debug)]
+pub struct Verifier {
+    root_dir: PathBuf,
+    state: Mutex<VerifierState>,
+}
+
+#[derive(Debug, Default)]
+struct VerifierState {
+    root: ExpectedEntries,
+    discrepancies: Vec<(PathBuf, Discrepancy)>,
+    /// The first error hit while checking directories outside of a task.
+    error: Option<io::Error>,
+}
+
+/// The entries expected in a directory, which are always named after their
+/// position.
+#[derive(Debug, Default, Copy, Clone)]
+struct ExpectedEntries {
+    dirs: usize,
+    files: u64,
+}
+
+impl Verifier {
+    pub fn new(root_dir: PathBuf) -> Self {
+        Self {
+            root_dir,
+            state: Mutex::default(),
+        }
+    }
+
+    pub const fn task(&self) -> TaskVerifier<'_> {
+        TaskVerifier {
+            verifier: self,
+            discrepancies: Vec::new(),
+        }
+    }
+
+    /// Checks a directory which was left empty and thus has no task.
+    pub fn empty_dir(&self, path: &Path) {
+        let mut discrepancies = Vec::new();
+        let result = self.extra_entries(path, ExpectedEntries::default(), &mut discrepancies);
+
+        let mut state = self.state.lock().unwrap();
+        state.discrepancies.extend(discrepancies);
+        if let Err(e) = result {
+            state.error.get_or_insert(e);
+        }
+    }
+
+    /// Looks for extra entries in the root directory and returns every
+    /// discrepancy sorted by path.
+    pub fn finish(self) -> io::Result<Vec<(PathBuf, Discrepancy)>> {
+        let VerifierState {
+            root,
+            mut discrepancies,
+            error,
+        } = mem::take(&mut *self.state.lock().unwrap());
+        if let Some(e) = error {
+            return Err(e);
+        }
+        self.extra_entries(&self.root_dir, root, &mut discrepancies)?;
+
+        discrepancies.sort_unstable();
+        Ok(discrepancies)
+    }
+
+    fn extra_entries(
+        &self,
+        dir: &Path,
+        expected: ExpectedEntries,
+        discrepancies: &mut Vec<(PathBuf, Discrepancy)>,
+    ) -> io::Result<()> {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            // The directory itself is reported as missing
+            Err(e) if matches!(e.kind(), NotFound | NotADirectory) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            if !expected.contains(&entry.file_name()) {
+                discrepancies.push((self.relative(&entry.path()), Discrepancy::Extra));
+            }
+        }
+        Ok(())
+    }
+
+    fn relative(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.root_dir)
+            .unwrap_or(path)
+            .to_path_buf()
+    }
+}
+
+impl ExpectedEntries {
+    fn contains(self, name: &OsStr) -> bool {
+        let Some(name) = name.to_str() else {
+            return false;
+        };
+        // Only canonically formatted numbers are generated, so round trip them to
+        // reject names such as `01`
+        name.strip_suffix(".dir").map_or_else(
+            || {
+                name.parse()
+                    .is_ok_and(|i| i < self.files && with_file_name(i, |s| s == name))
+            },
+            |dir| {
+                dir.parse()
+                    .is_ok_and(|i| i < self.dirs && with_dir_name(i, |s| s == name))
+            },
+        )
+    }
+}
+
+/// Collects the findings of a single task.
+pub struct TaskVerifier<'a> {
+    verifier: &'a Verifier,
+    discrepancies: Vec<(PathBuf, Discrepancy)>,
+}
+
+impl TaskVerifier<'_> {
+    pub fn dir(&mut self, path: &Path) -> io::Result<()> {
+        let is_dir = match symlink_metadata(path) {
+            Ok(metadata) => metadata.is_dir(),
+            Err(e) if e.kind() == NotFound => false,
+            Err(e) => return Err(e),
+        };
+
+        if !is_dir {
+            self.report(path, Discrepancy::Missing);
+        }
+        Ok(())
+    }
+
+    pub fn file(&mut self, path: &Path, expected: &FileOutcome) -> io::Result<()> {
+        let &FileOutcome { bytes, hash, .. } = expected;
+
+        let discrepancy = match symlink_metadata(path) {
+            Ok(metadata) if !metadata.is_file() => Some(Discrepancy::Missing),
+            Ok(metadata) if metadata.len() != bytes => Some(Discrepancy::Resized),
+            Ok(_) => {
+                let mut hasher = ContentHasher::new(io::sink());
+                io::copy(&mut File::open(path)?, &mut hasher)?;
+                (Some(hasher.finish()) != hash).then_some(Discrepancy::ContentMismatch)
+            }
+            Err(e) if e.kind() == NotFound => Some(Discrepancy::Missing),
+            Err(e) => return Err(e),
+        };
+
+        if let Some(discrepancy) = discrepancy {
+            self.report(path, discrepancy);
+        }
+        Ok(())
+    }
+
+    /// Looks for extra entries in the task's directory given the number of
+    /// directories and files the tree places there.
+    pub fn finish(mut self, dir: &Path, dirs: usize, files: u64) -> io::Result<()> {
+        let expected = ExpectedEntries { dirs, files };
+        let root = dir == self.verifier.root_dir;
+        if !root {
+            self.verifier
+                .extra_entries(dir, expected, &mut self.discrepancies)?;
+        }
+
+        let Self {
+            verifier,
+            discrepancies,
+        } = self;
+        let mut state = verifier.state.lock().unwrap();
+        if root {
+            state.root.dirs = state.root.dirs.max(dirs);
+            state.root.files = state.root.files.max(files);
+        }
+        state.discrepancies.extend(discrepancies);
+        drop(state);
+        Ok(())
+    }
+
+    fn report(&mut self, path: &Path, discrepancy: Discrepancy) {
+        let path = self.verifier.relative(path);
+        self.discrepancies.push((path, discrepancy));
+    }
+}
@@ -6,26 +6,41 @@
 
 use std::{
     cmp::max,
+    fmt,
     fmt::Write,
-    fs::create_dir_all,
+    fs::{create_dir, create_dir_all},
     hash::{DefaultHasher, Hash, Hasher},
+    io::ErrorKind::AlreadyExists,
     num::{NonZeroU64, NonZeroUsize},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::ExitCode,
+    sync::{Arc, mpsc::Receiver},
     thread,
+    time::{Duration, Instant},
 };
 
 use bon::Builder;
 use bytesize::ByteSize;
 use error_stack::{Report, Result, ResultExt};
 use log::{Level, log};
-use rand::SeedableRng;
+use rand::{SeedableRng, distr::Bernoulli};
 use rand_xoshiro::Xoshiro256PlusPlus;
 use thiserror::Error;
 use thousands::Separable;
 
-use crate::core::{
-    DynamicGenerator, GeneratorBytes, GeneratorStats, StaticGenerator, run, truncatable_normal,
+pub use crate::core::{
+    ArchiveFormat, ArchiveOutput, Backend, CancellationToken, CompletedTask, ContentSeed, Entry,
+    EntryKind, EntryMetadata, FileContents, Filesystem, ManifestFormat, MemoryBackend, Observer,
+    PlannedEntry, Rates, SyncPolicy, ZipCompression,
+};
+use crate::{
+    core::{
+        Archive, Checkpoint, ContentHasher, Discrepancy, DynamicGenerator, GeneratorBytes,
+        GeneratorStats, Journal, Manifest, Planner, Progress, ProgressTarget, StaticGenerator,
+        StopCondition, Syncer, TaskMode, Throttle, Throttled, TreeDigest, Verifier, run, run_flat,
+        truncatable_normal,
+    },
+    utils::{SeedHasher, push_json_string},
 };
 
 #[derive(Error, Debug)]
@@ -38,12 +53,52 @@ pub enum Error {
     InvalidEnvironment,
     #[error("Failed to create the async runtime.")]
     RuntimeCreation,
+    #[error("The generator configuration is invalid.")]
+    InvalidConfiguration,
+    #[error("The tree does not match its expected contents.")]
+    VerificationFailed,
+    #[error("Generation was stopped before the tree was complete.")]
+    Cancelled,
+}
+
+/// How the configuration and results of a run are reported.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// English sentences.
+    #[default]
+    Human,
+    /// A single JSON object.
+    Json,
+}
+
+/// How a tree's seed is derived from its parameters.
+///
+/// The same parameters and seed version always produce the same tree, so
+/// pinning the version reproduces trees generated by older releases. The seeds
+/// of a series' later snapshots are always derived with the stable hash.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SeedVersion {
+    /// The standard library's `DefaultHasher`, used by ftzz 4.0 and earlier.
+    ///
+    /// Its output is not guaranteed to be the same across Rust releases.
+    V1,
+    /// The 64-bit FNV-1a hash of the parameters' little-endian encoding.
+    #[default]
+    V2,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub struct NumFilesWithRatio {
-    num_files: NonZeroU64,
-    file_to_dir_ratio: NonZeroU64,
+pub struct NumFilesWithRatio(FileCounts);
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum FileCounts {
+    Files {
+        num_files: NonZeroU64,
+        file_to_dir_ratio: NonZeroU64,
+    },
+    DirsOnly {
+        num_dirs: NonZeroU64,
+    },
 }
 
 #[derive(Error, Debug)]
@@ -74,17 +129,41 @@ impl NumFilesWithRatio {
             });
         }
 
-        Ok(Self {
+        Ok(Self(FileCounts::Files {
             num_files,
             file_to_dir_ratio,
-        })
+        }))
     }
 
     #[must_use]
     pub fn from_num_files(num_files: NonZeroU64) -> Self {
-        Self {
+        Self(FileCounts::Files {
             num_files,
             file_to_dir_ratio: NonZeroU64::new(max(num_files.get() / 1000, 1)).unwrap(),
+        })
+    }
+
+    /// Generate a tree of approximately `num_dirs` directories without any
+    /// files.
+    #[must_use]
+    pub const fn dirs_only(num_dirs: NonZeroU64) -> Self {
+        Self(FileCounts::DirsOnly { num_dirs })
+    }
+
+    const fn num_files(self) -> u64 {
+        match self.0 {
+            FileCounts::Files { num_files, .. } => num_files.get(),
+            FileCounts::DirsOnly { .. } => 0,
+        }
+    }
+
+    fn num_dirs(self) -> f64 {
+        match self.0 {
+            FileCounts::Files {
+                num_files,
+                file_to_dir_ratio,
+            } => num_files.get() as f64 / file_to_dir_ratio.get() as f64,
+            FileCounts::DirsOnly { num_dirs } => num_dirs.get() as f64,
         }
     }
 }
@@ -103,8 +182,76 @@ pub struct Generator {
     bytes_exact: bool,
     #[builder(default = 5)]
     max_depth: u32,
+    #[builder(default = false)]
+    flat: bool,
+    /// The fraction of leaf directories (between 0 and 1) which will be left
+    /// empty.
+    #[builder(default = 0.)]
+    empty_dirs: f64,
+    /// Generate files in a subdirectory of the root directory (namespaced by
+    /// the seed) if the root directory is not empty.
+    #[builder(default = false)]
+    allow_non_empty: bool,
+    /// Complete a previously interrupted generation by skipping the
+    /// directories its journal records as complete and only regenerating
+    /// missing or incomplete files elsewhere.
+    ///
+    /// Resumable runs writing to the filesystem keep the journal in the root
+    /// directory until the tree is complete, so a run which may be interrupted
+    /// should be started with this set too. Trees resumed with a manifest are
+    /// rescanned in full so that every entry is listed.
+    #[builder(default = false)]
+    resume: bool,
+    /// Stream a record describing each generated file and directory to this
+    /// file.
+    #[builder(into)]
+    manifest: Option<PathBuf>,
+    #[builder(default)]
+    manifest_format: ManifestFormat,
+    /// Stream the tree into this archive instead of writing it to disk.
+    #[builder(into)]
+    archive: Option<ArchiveOutput>,
+    #[builder(default)]
+    archive_format: ArchiveFormat,
+    /// Write the tree through this backend instead of to the filesystem.
+    backend: Option<Arc<dyn Backend>>,
+    /// Compute the tree without writing anything to disk.
+    #[builder(default = false)]
+    dry_run: bool,
+    /// Report an order-independent digest of the generated tree's paths and
+    /// contents.
+    #[builder(default = false)]
+    digest: bool,
+    /// Report progress on stderr while generating.
+    #[builder(default = false)]
+    progress: bool,
+    /// Receives events as entries are generated.
+    observer: Option<Arc<dyn Observer>>,
+    /// Stop queueing new work once this token is cancelled.
+    cancellation: Option<CancellationToken>,
+    /// Stop queueing new work once this much time has elapsed.
+    time_limit: Option<Duration>,
+    /// The number of worker threads creating files and directories, defaulting
+    /// to the available parallelism.
+    ///
+    /// The generated tree is the same regardless of the number of jobs.
+    jobs: Option<NonZeroUsize>,
+    /// The number of tasks queued before waiting for the oldest ones to
+    /// complete, defaulting to the square of the number of jobs.
+    queue_depth: Option<NonZeroUsize>,
+    /// Write at most this many files per second across all jobs.
+    max_files_per_sec: Option<NonZeroU64>,
+    /// Write at most this many bytes per second across all jobs.
+    max_bytes_per_sec: Option<NonZeroU64>,
+    /// When created entries are flushed to durable storage.
+    #[builder(default)]
+    sync: SyncPolicy,
+    #[builder(default)]
+    output_format: OutputFormat,
     #[builder(default = 0)]
     seed: u64,
+    #[builder(default)]
+    seed_version: SeedVersion,
 }
 
 #[cfg(test)]
@@ -121,13 +268,50 @@ mod tests {
             .build();
 
         assert_eq!(g.root_dir, PathBuf::from("abc"));
-        assert_eq!(g.num_files_with_ratio.num_files.get(), 1);
+        let FileCounts::Files {
+            num_files,
+            file_to_dir_ratio,
+        } = g.num_files_with_ratio.0
+        else {
+            panic!("Expected files to be generated")
+        };
+        assert_eq!(num_files.get(), 1);
         assert!(!g.files_exact);
         assert_eq!(g.num_bytes, 0);
         assert!(!g.bytes_exact);
         assert_eq!(g.max_depth, 5);
-        assert_eq!(g.num_files_with_ratio.file_to_dir_ratio.get(), 1);
+        assert!(!g.flat);
+        assert_eq!(g.empty_dirs, 0.);
+        assert!(!g.allow_non_empty);
+        assert!(!g.resume);
+        assert_eq!(g.manifest, None);
+        assert_eq!(g.manifest_format, ManifestFormat::JsonLines);
+        assert_eq!(g.archive, None);
+        assert_eq!(g.archive_format, ArchiveFormat::Tar);
+        assert!(g.backend.is_none());
+        assert!(!g.dry_run);
+        assert!(!g.digest);
+        assert!(!g.progress);
+        assert!(g.observer.is_none());
+        assert!(g.cancellation.is_none());
+        assert_eq!(g.time_limit, None);
+        assert_eq!(g.jobs, None);
+        assert_eq!(g.queue_depth, None);
+        assert_eq!(g.max_files_per_sec, None);
+        assert_eq!(g.max_bytes_per_sec, None);
+        assert_eq!(g.sync, SyncPolicy::None);
+        assert_eq!(g.output_format, OutputFormat::Human);
+        assert_eq!(file_to_dir_ratio.get(), 1);
         assert_eq!(g.seed, 0);
+        assert_eq!(g.seed_version, SeedVersion::V2);
+    }
+
+    #[test]
+    fn dirs_only_has_no_files() {
+        let r = NumFilesWithRatio::dirs_only(NonZeroU64::new(42).unwrap());
+
+        assert_eq!(r.num_files(), 0);
+        assert_eq!(r.num_dirs(), 42.);
     }
 
     #[test]
@@ -136,13 +320,280 @@ mod tests {
 
         r.unwrap_err();
     }
+
+    #[test]
+    fn stable_tree_seed_never_changes() {
+        let files = NumFilesWithRatio::from_num_files(NonZeroU64::new(1000).unwrap());
+        let dirs = NumFilesWithRatio::dirs_only(NonZeroU64::new(42).unwrap());
+
+        assert_eq!(
+            tree_seed(files, 5, 0, SeedVersion::V2),
+            0xa03c_0e20_6af3_8e76
+        );
+        assert_eq!(
+            tree_seed(dirs, 5, 7, SeedVersion::V2),
+            0xbbc2_f78d_3a87_0af4
+        );
+    }
 }
 
 impl Generator {
-    pub fn generate(self, output: &mut impl Write) -> Result<(), Error> {
-        let options = validated_options(self)?;
-        print_configuration_info(&options, output)?;
-        print_stats(run_generator(options)?, output);
+    /// Generates the tree, describing the configuration and results in the
+    /// requested output format.
+    pub fn generate(self, output: &mut impl Write) -> Result<GeneratorReport, Error> {
+        let output_format = self.output_format;
+        let options = validated_options(self, false)?;
+        match output_format {
+            OutputFormat::Human => {
+                print_configuration_info(&options, output)?;
+                let report = generate_report(options)?;
+                // Ignore I/O errors since it'd be dumb to fail if we actually succeeded in
+                // creating all the files
+                let _ = writeln!(output, "{report}");
+                completed(report)
+            }
+            OutputFormat::Json => {
+                let configuration = configuration_json(&options);
+                let report = generate_report(options)?;
+                print_json(&configuration, &report, output)?;
+                completed(report)
+            }
+        }
+    }
+
+    /// Generates the tree without printing anything.
+    ///
+    /// If the run is stopped early, the returned [`Error::Cancelled`] report
+    /// carries the partial [`GeneratorReport`] as an attachment.
+    pub fn run(self) -> Result<GeneratorReport, Error> {
+        completed(generate_report(validated_options(self, false)?)?)
+    }
+
+    /// Regenerates the expected tree without writing it and compares it
+    /// against the one on disk, failing if any entry is missing, extra,
+    /// resized, or has different contents.
+    pub fn verify(self, output: &mut impl Write) -> Result<(), Error> {
+        let mut options = validated_options(self, true)?;
+        let verifier = Arc::new(Verifier::new(options.root_dir.clone()));
+        options.verifier = Some(verifier.clone());
+
+        let (stats, _) = run_generator(options)?;
+        if stats.stopped_early {
+            // Unvisited entries would otherwise be reported as extra
+            return Err(Report::new(Error::Cancelled))
+                .attach_printable("The tree was only partially verified.")
+                .attach(ExitCode::from(sysexits::ExitCode::TempFail));
+        }
+        let discrepancies = Arc::into_inner(verifier)
+            .expect("All tasks have completed")
+            .finish()
+            .attach_printable("Failed to search for extra entries")
+            .change_context(Error::Io)
+            .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+        print_verification(stats, &discrepancies, output)
+    }
+
+    /// Computes the tree without creating anything, lazily yielding its
+    /// entries in the order a real run would create them.
+    ///
+    /// Planning happens on a background thread which pauses whenever the
+    /// consumer falls behind.
+    pub fn plan(mut self) -> Result<PlannedEntries, Error> {
+        self.dry_run = true;
+        let mut options = validated_options(self, false)?;
+        let (planner, entries) = Planner::new();
+        options.planner = Some(Arc::new(planner));
+
+        let run = thread::Builder::new()
+            .name("ftzz-planner".to_string())
+            .spawn(move || completed(generate_report(options)?))
+            .change_context(Error::RuntimeCreation)
+            .attach(ExitCode::from(sysexits::ExitCode::OsErr))?;
+        Ok(PlannedEntries { entries, run })
+    }
+
+    pub(crate) const fn root_dir_mut(&mut self) -> &mut PathBuf {
+        &mut self.root_dir
+    }
+
+    /// Explains why a snapshot series can't be generated with this
+    /// configuration, given that each snapshot is copied from a complete tree
+    /// in its predecessor's directory.
+    pub(crate) const fn series_conflict(&self) -> Option<&'static str> {
+        if self.dry_run || self.archive.is_some() || self.backend.is_some() {
+            Some("Snapshot series must be written to the filesystem.")
+        } else if self.resume {
+            Some("Snapshot series cannot be resumed.")
+        } else if self.allow_non_empty {
+            Some("Snapshot series cannot be generated in a non-empty directory.")
+        } else if self.manifest.is_some() {
+            Some("Snapshot series cannot be listed in a manifest.")
+        } else {
+            None
+        }
+    }
+}
+
+/// The entries of a planned tree, yielded in the order a real run would create
+/// them.
+///
+/// Iteration stops early if planning fails, in which case the error is only
+/// returned by [`PlannedEntries::finish`].
+///
+/// See [`Generator::plan`].
+#[derive(Debug)]
+pub struct PlannedEntries {
+    entries: Receiver<PlannedEntry>,
+    run: thread::JoinHandle<Result<GeneratorReport, Error>>,
+}
+
+impl Iterator for PlannedEntries {
+    type Item = PlannedEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.recv().ok()
+    }
+}
+
+impl PlannedEntries {
+    /// Skips any remaining entries and returns the report of the equivalent
+    /// dry run.
+    pub fn finish(self) -> Result<GeneratorReport, Error> {
+        let Self { entries, run } = self;
+        entries.iter().for_each(drop);
+        run.join()
+            .map_err(|_| Report::new(Error::TaskJoin))
+            .attach(ExitCode::from(sysexits::ExitCode::Software))?
+    }
+}
+
+/// The results of a generator run.
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratorReport {
+    /// The directory in which the tree was generated, including any
+    /// namespace subdirectory.
+    pub root_dir: PathBuf,
+    /// The seed from which the tree was derived.
+    pub seed: u64,
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+    /// The number of files without any contents.
+    pub empty_files: u64,
+    /// The number of pre-existing files kept while resuming.
+    pub reused_files: u64,
+    /// The tree's digest if one was requested.
+    pub digest: Option<u64>,
+    /// Whether the tree was only computed rather than written to disk.
+    pub dry_run: bool,
+    /// Whether the tree was streamed into an archive rather than written to
+    /// disk.
+    pub archived: bool,
+    /// Whether the run was cancelled or ran out of time, leaving the tree
+    /// incomplete.
+    pub stopped_early: bool,
+    /// The rates at which files and bytes were written if the run was
+    /// throttled.
+    pub rates: Option<Rates>,
+    /// The time spent flushing entries to durable storage, summed across
+    /// jobs, if a sync policy was requested.
+    pub sync_elapsed: Option<Duration>,
+    /// How long each phase of the run took.
+    pub phases: Phases,
+    pub elapsed: Duration,
+}
+
+/// The wall-clock time spent in each phase of a run.
+#[non_exhaustive]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Phases {
+    /// Starting the runtime and creating the manifest or archive.
+    pub setup: Duration,
+    /// Scheduling tasks and waiting for them to complete.
+    pub generation: Duration,
+    /// Syncing the tree and flushing the journal, manifest, or archive.
+    pub finish: Duration,
+}
+
+impl fmt::Display for GeneratorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let &Self {
+            root_dir: _,
+            seed: _,
+            files,
+            dirs,
+            bytes,
+            empty_files: _,
+            reused_files,
+            digest,
+            dry_run,
+            archived,
+            stopped_early,
+            rates,
+            sync_elapsed,
+            phases: _,
+            elapsed: _,
+        } = self;
+
+        write!(
+            f,
+            "{verb} {} {files_maybe_plural}{bytes_info} across {} {directories_maybe_plural}.",
+            files.separate_with_commas(),
+            dirs.separate_with_commas(),
+            verb = if dry_run {
+                "Would create"
+            } else if archived {
+                "Archived"
+            } else {
+                "Created"
+            },
+            files_maybe_plural = if files == 1 { "file" } else { "files" },
+            directories_maybe_plural = if dirs == 1 {
+                "directory"
+            } else {
+                "directories"
+            },
+            bytes_info = if bytes > 0 {
+                format!(" ({})", ByteSize(bytes).display().si())
+            } else {
+                String::new()
+            },
+        )?;
+        if reused_files > 0 {
+            write!(
+                f,
+                " Reused {} pre-existing {files_maybe_plural}.",
+                reused_files.separate_with_commas(),
+                files_maybe_plural = if reused_files == 1 { "file" } else { "files" },
+            )?;
+        }
+        if let Some(Rates {
+            files_per_sec,
+            bytes_per_sec,
+        }) = rates
+        {
+            write!(
+                f,
+                " Wrote {} {files_maybe_plural}{bytes_info} per second.",
+                files_per_sec.separate_with_commas(),
+                files_maybe_plural = if files_per_sec == 1 { "file" } else { "files" },
+                bytes_info = if bytes > 0 {
+                    format!(" ({})", ByteSize(bytes_per_sec).display().si())
+                } else {
+                    String::new()
+                },
+            )?;
+        }
+        if let Some(sync_elapsed) = sync_elapsed {
+            write!(f, " Spent {sync_elapsed:.2?} syncing.")?;
+        }
+        if let Some(digest) = digest {
+            write!(f, " The tree's digest is {digest:016x}.")?;
+        }
+        if stopped_early {
+            f.write_str(" Stopped before the tree was complete.")?;
+        }
         Ok(())
     }
 }
@@ -150,7 +601,7 @@ impl Generator {
 #[derive(Debug)]
 struct Configuration {
     root_dir: PathBuf,
-    files: NonZeroU64,
+    files: u64,
     bytes: u64,
     files_exact: bool,
     bytes_exact: bool,
@@ -158,10 +609,79 @@ struct Configuration {
     dirs_per_dir: f64,
     bytes_per_file: f64,
     max_depth: u32,
+    flat: bool,
+    empty_dirs: f64,
+    resume: bool,
+    manifest: Option<(PathBuf, ManifestFormat)>,
+    archive: Option<(ArchiveOutput, ArchiveFormat)>,
+    backend: Option<Arc<dyn Backend>>,
+    dry_run: bool,
+    digest: bool,
+    progress: bool,
+    observer: Option<Arc<dyn Observer>>,
+    cancellation: Option<CancellationToken>,
+    time_limit: Option<Duration>,
+    jobs: Option<NonZeroUsize>,
+    queue_depth: Option<NonZeroUsize>,
+    throttle: Option<Arc<Throttle>>,
+    sync: Option<Arc<Syncer>>,
+    journal: Option<Arc<Journal>>,
+    verifier: Option<Arc<Verifier>>,
+    planner: Option<Arc<Planner>>,
     seed: u64,
+    namespace: Option<Namespace>,
     human_info: HumanInfo,
 }
 
+impl Configuration {
+    /// Identifies the tree described by the configuration so that a journal
+    /// is only used to resume the tree it was written for.
+    fn fingerprint(&self) -> u64 {
+        let &Self {
+            files,
+            bytes,
+            files_exact,
+            bytes_exact,
+            fill_byte,
+            dirs_per_dir,
+            bytes_per_file,
+            max_depth,
+            flat,
+            empty_dirs,
+            digest,
+            seed,
+            ..
+        } = self;
+
+        let mut hasher = ContentHasher::new(());
+        for field in [
+            files,
+            bytes,
+            u64::from(files_exact),
+            u64::from(bytes_exact),
+            fill_byte.map_or(u64::MAX, u64::from),
+            dirs_per_dir.to_bits(),
+            bytes_per_file.to_bits(),
+            u64::from(max_depth),
+            u64::from(flat),
+            empty_dirs.to_bits(),
+            u64::from(digest),
+            seed,
+        ] {
+            hasher.update(&field.to_le_bytes());
+        }
+        hasher.finish()
+    }
+}
+
+/// Describes the subdirectory of a non-empty root directory in which files
+/// are generated.
+#[derive(Debug)]
+struct Namespace {
+    parent: PathBuf,
+    conflicts: Vec<PathBuf>,
+}
+
 #[derive(Debug)]
 struct HumanInfo {
     dirs_per_dir: usize,
@@ -179,33 +699,121 @@ fn validated_options(
         fill_byte,
         bytes_exact,
         max_depth,
+        flat,
+        empty_dirs,
+        allow_non_empty,
+        resume,
+        manifest,
+        manifest_format,
+        archive,
+        archive_format,
+        backend,
+        dry_run,
+        digest,
+        progress,
+        observer,
+        cancellation,
+        time_limit,
+        jobs,
+        queue_depth,
+        max_files_per_sec,
+        max_bytes_per_sec,
+        sync,
+        output_format: _,
         seed,
+        seed_version,
     }: Generator,
+    verify: bool,
 ) -> Result<Configuration, Error> {
-    create_dir_all(&root_dir)
-        .attach_printable_lazy(|| format!("Failed to create directory {root_dir:?}"))
-        .change_context(Error::InvalidEnvironment)
-        .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
-    if root_dir
-        .read_dir()
-        .attach_printable_lazy(|| format!("Failed to read directory {root_dir:?}"))
-        .change_context(Error::InvalidEnvironment)
-        .attach(ExitCode::from(sysexits::ExitCode::IoErr))?
-        .count()
-        != 0
-    {
+    if !(0. ..=1.).contains(&empty_dirs) {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable(format!(
+                "The fraction of empty directories ({empty_dirs}) must be between 0 and 1."
+            ))
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    if num_files_with_ratio.num_files() == 0 && num_bytes > 0 {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable("Bytes cannot be generated without any files.")
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    if archive.is_some() && (verify || resume || dry_run) {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable("Archives are always generated from scratch.")
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    if backend.is_some() && (verify || archive.is_some()) {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable("Only trees written to the filesystem can be verified or archived.")
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    let throttled = max_files_per_sec.is_some() || max_bytes_per_sec.is_some();
+    if throttled && (verify || archive.is_some()) {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable("Archived or verified trees cannot be throttled.")
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    if throttled && dry_run {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable("Dry runs cannot be throttled.")
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    let throttle = throttled.then(|| Arc::new(Throttle::new(max_files_per_sec, max_bytes_per_sec)));
+    if sync != SyncPolicy::None && (verify || archive.is_some()) {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable("Archived or verified trees cannot be synced.")
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    let sync = (sync != SyncPolicy::None && !dry_run).then(|| Arc::new(Syncer::new(sync)));
+
+    let backend_is_filesystem = backend.is_none();
+    let root_is_empty = if verify {
+        if !root_dir.is_dir() {
+            return Err(Report::new(Error::InvalidEnvironment))
+                .attach_printable(format!("The root directory {root_dir:?} does not exist."))
+                .attach(ExitCode::from(sysexits::ExitCode::NoInput));
+        }
+        // The tree is verified in place
+        true
+    } else if archive.is_some() {
+        // Nothing is written to the root directory
+        true
+    } else if let Some(backend) = &backend {
+        if !dry_run {
+            backend
+                .create_dir(&root_dir, &EntryMetadata::DIR)
+                .attach_printable_lazy(|| format!("Failed to create directory {root_dir:?}"))
+                .change_context(Error::InvalidEnvironment)
+                .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+        }
+        // The backend's existing entries can't be inspected
+        true
+    } else if dry_run {
+        !root_dir.exists() || is_empty_dir(&root_dir)?
+    } else {
+        create_dir_all(&root_dir)
+            .attach_printable_lazy(|| format!("Failed to create directory {root_dir:?}"))
+            .change_context(Error::InvalidEnvironment)
+            .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+        is_empty_dir(&root_dir)?
+    };
+    if !root_is_empty && !allow_non_empty && !resume {
         return Err(Report::new(Error::InvalidEnvironment))
             .attach_printable(format!("The root directory {root_dir:?} must be empty."))
             .attach(ExitCode::from(sysexits::ExitCode::DataErr));
     }
 
-    let num_files = num_files_with_ratio.num_files.get() as f64;
-    let bytes_per_file = num_bytes as f64 / num_files;
+    let num_files = num_files_with_ratio.num_files() as f64;
+    let bytes_per_file = if num_bytes > 0 {
+        num_bytes as f64 / num_files
+    } else {
+        0.
+    };
 
-    if max_depth == 0 {
-        return Ok(Configuration {
+    let mut config = if max_depth == 0 || flat {
+        Configuration {
             root_dir,
-            files: num_files_with_ratio.num_files,
+            files: num_files_with_ratio.num_files(),
             bytes: num_bytes,
             files_exact,
             bytes_exact,
@@ -213,41 +821,212 @@ fn validated_options(
             dirs_per_dir: 0.,
             bytes_per_file,
             max_depth: 0,
+            flat,
+            empty_dirs,
+            resume,
+            manifest: manifest.map(|path| (path, manifest_format)),
+            archive: archive.map(|output| (output, archive_format)),
+            backend,
+            dry_run,
+            digest,
+            progress,
+            observer,
+            cancellation,
+            time_limit,
+            jobs,
+            queue_depth,
+            throttle,
+            sync,
+            journal: None,
+            verifier: None,
+            planner: None,
             seed,
+            namespace: None,
             human_info: HumanInfo {
                 dirs_per_dir: 0,
                 total_dirs: 1,
                 bytes_per_files: bytes_per_file.round() as usize,
             },
-        });
-    }
+        }
+    } else {
+        let num_dirs = num_files_with_ratio.num_dirs();
+        // This formula was derived from the following equation:
+        // num_dirs = unknown_num_dirs_per_dir^max_depth
+        let dirs_per_dir = num_dirs.powf(1f64 / f64::from(max_depth));
+
+        Configuration {
+            root_dir,
+            files: num_files_with_ratio.num_files(),
+            bytes: num_bytes,
+            files_exact,
+            bytes_exact,
+            fill_byte,
+            bytes_per_file,
+            dirs_per_dir,
+            max_depth,
+            flat,
+            empty_dirs,
+            resume,
+            manifest: manifest.map(|path| (path, manifest_format)),
+            archive: archive.map(|output| (output, archive_format)),
+            backend,
+            dry_run,
+            digest,
+            progress,
+            observer,
+            cancellation,
+            time_limit,
+            jobs,
+            queue_depth,
+            throttle,
+            sync,
+            journal: None,
+            verifier: None,
+            planner: None,
+            namespace: None,
+            seed: tree_seed(num_files_with_ratio, max_depth, seed, seed_version),
+            human_info: HumanInfo {
+                dirs_per_dir: dirs_per_dir.round() as usize,
+                total_dirs: num_dirs.round() as usize,
+                bytes_per_files: bytes_per_file.round() as usize,
+            },
+        }
+    };
 
-    let num_dirs = num_files / (num_files_with_ratio.file_to_dir_ratio.get() as f64);
-    // This formula was derived from the following equation:
-    // num_dirs = unknown_num_dirs_per_dir^max_depth
-    let dirs_per_dir = num_dirs.powf(1f64 / f64::from(max_depth));
+    if !root_is_empty && allow_non_empty {
+        config.namespace = Some(namespace_root_dir(
+            &mut config.root_dir,
+            config.seed,
+            resume,
+            dry_run,
+        )?);
+    }
+    if resume && backend_is_filesystem && !(dry_run || verify || config.archive.is_some()) {
+        // Manifests list every entry, so they can't be resumed by skipping tasks
+        let load = config.manifest.is_none();
+        config.journal = Some(Arc::new(
+            Journal::open(&config.root_dir, config.fingerprint(), load)
+                .attach_printable_lazy(|| {
+                    format!("Failed to open the journal in {:?}", config.root_dir)
+                })
+                .change_context(Error::InvalidEnvironment)
+                .attach(ExitCode::from(sysexits::ExitCode::IoErr))?,
+        ));
+    }
+    Ok(config)
+}
 
-    Ok(Configuration {
-        root_dir,
-        files: num_files_with_ratio.num_files,
-        bytes: num_bytes,
-        files_exact,
-        bytes_exact,
-        fill_byte,
-        bytes_per_file,
-        dirs_per_dir,
-        max_depth,
-        seed: {
+/// Derives the seed of a hierarchical tree such that differently shaped trees
+/// don't share the same PRNG stream.
+///
+/// Version 2 hashes a tag byte (0 for files, 1 for directories only), the file
+/// and directory counts, the maximum depth, and the seed in that order.
+fn tree_seed(
+    num_files_with_ratio: NumFilesWithRatio,
+    max_depth: u32,
+    seed: u64,
+    version: SeedVersion,
+) -> u64 {
+    match version {
+        SeedVersion::V1 => {
             let mut hasher = DefaultHasher::new();
-            (num_files_with_ratio, max_depth, seed).hash(&mut hasher);
+            match num_files_with_ratio.0 {
+                FileCounts::Files {
+                    num_files,
+                    file_to_dir_ratio,
+                } => (num_files, file_to_dir_ratio, max_depth, seed).hash(&mut hasher),
+                FileCounts::DirsOnly { num_dirs } => {
+                    (num_dirs, max_depth, seed).hash(&mut hasher);
+                }
+            }
             hasher.finish()
-        },
-        human_info: HumanInfo {
-            dirs_per_dir: dirs_per_dir.round() as usize,
-            total_dirs: num_dirs.round() as usize,
-            bytes_per_files: bytes_per_file.round() as usize,
-        },
-    })
+        }
+        SeedVersion::V2 => {
+            let mut hasher = SeedHasher::new();
+            match num_files_with_ratio.0 {
+                FileCounts::Files {
+                    num_files,
+                    file_to_dir_ratio,
+                } => {
+                    hasher.update(&[0]);
+                    hasher.update(&num_files.get().to_le_bytes());
+                    hasher.update(&file_to_dir_ratio.get().to_le_bytes());
+                }
+                FileCounts::DirsOnly { num_dirs } => {
+                    hasher.update(&[1]);
+                    hasher.update(&num_dirs.get().to_le_bytes());
+                }
+            }
+            hasher.update(&max_depth.to_le_bytes());
+            hasher.update(&seed.to_le_bytes());
+            hasher.finish()
+        }
+    }
+}
+
+fn is_empty_dir(dir: &Path) -> Result<bool, Error> {
+    Ok(dir
+        .read_dir()
+        .attach_printable_lazy(|| format!("Failed to read directory {dir:?}"))
+        .change_context(Error::InvalidEnvironment)
+        .attach(ExitCode::from(sysexits::ExitCode::IoErr))?
+        .next()
+        .is_none())
+}
+
+/// Moves the root directory into a new subdirectory named after the seed,
+/// skipping any pre-existing entries with the same name.
+///
+/// When resuming, the most recently created subdirectory is reused instead.
+fn namespace_root_dir(
+    root_dir: &mut PathBuf,
+    seed: u64,
+    resume: bool,
+    dry_run: bool,
+) -> Result<Namespace, Error> {
+    let name = |attempt| {
+        if attempt == 0 {
+            format!("ftzz-{seed:016x}")
+        } else {
+            format!("ftzz-{seed:016x}.{attempt}")
+        }
+    };
+
+    let parent = root_dir.clone();
+    let mut conflicts = Vec::new();
+    for attempt in 0u64.. {
+        root_dir.push(name(attempt));
+
+        let created = if !dry_run {
+            create_dir(&*root_dir)
+        } else if root_dir.exists() {
+            Err(AlreadyExists.into())
+        } else {
+            Ok(())
+        };
+        match created {
+            Ok(()) => break,
+            Err(e)
+                if e.kind() == AlreadyExists
+                    && root_dir.is_dir()
+                    && (is_empty_dir(root_dir)?
+                        || resume && !parent.join(name(attempt + 1)).exists()) =>
+            {
+                break;
+            }
+            Err(e) if e.kind() == AlreadyExists => {
+                conflicts.push(root_dir.clone());
+                root_dir.pop();
+            }
+            Err(e) => {
+                return Err(Report::new(e))
+                    .attach_printable(format!("Failed to create directory {root_dir:?}"))
+                    .change_context(Error::InvalidEnvironment)
+                    .attach(ExitCode::from(sysexits::ExitCode::IoErr));
+            }
+        }
+    }
+    Ok(Namespace { parent, conflicts })
 }
 
 #[cfg_attr(
@@ -256,7 +1035,7 @@ fn validated_options(
 )]
 fn print_configuration_info(
     &Configuration {
-        root_dir: _,
+        ref root_dir,
         files,
         bytes,
         files_exact,
@@ -265,7 +1044,27 @@ fn print_configuration_info(
         dirs_per_dir: _,
         bytes_per_file: _,
         max_depth,
+        flat: _,
+        empty_dirs,
+        resume: _,
+        manifest: _,
+        archive: _,
+        backend: _,
+        dry_run: _,
+        digest: _,
+        progress: _,
+        observer: _,
+        cancellation: _,
+        time_limit: _,
+        jobs: _,
+        queue_depth: _,
+        throttle: _,
+        sync: _,
+        journal: _,
+        verifier: _,
+        planner: _,
         seed: _,
+        ref namespace,
         human_info:
             HumanInfo {
                 dirs_per_dir,
@@ -277,15 +1076,36 @@ fn print_configuration_info(
 ) -> Result<(), Error> {
     writeln!(
         output,
-        "{file_count_type} {} {files_maybe_plural} will be generated in approximately {} \
-         {directories_maybe_plural} distributed across a tree of maximum depth {} where each \
-         directory contains approximately {} other {dpd_directories_maybe_plural}.{bytes_info}",
+        "{namespace_info}{file_count_type} {} {files_maybe_plural} will be generated in \
+         approximately {} {directories_maybe_plural} distributed across a tree of maximum depth \
+         {} where each directory contains approximately {} other \
+         {dpd_directories_maybe_plural}.{empty_dirs_info}{bytes_info}",
         files.separate_with_commas(),
         total_dirs.separate_with_commas(),
         max_depth.separate_with_commas(),
         dirs_per_dir.separate_with_commas(),
-        file_count_type = if files_exact { "Exactly" } else { "About" },
-        files_maybe_plural = if files.get() == 1 { "file" } else { "files" },
+        namespace_info =
+            namespace
+                .as_ref()
+                .map_or_else(String::new, |Namespace { parent, conflicts }| {
+                    let mut info = String::new();
+                    for conflict in conflicts {
+                        let _ =
+                            writeln!(info, "Skipped conflicting pre-existing entry {conflict:?}.");
+                    }
+                    let _ = writeln!(
+                        info,
+                        "The root directory {parent:?} is not empty, so files will be generated \
+                         in {root_dir:?} instead."
+                    );
+                    info
+                }),
+        file_count_type = if files_exact || files == 0 {
+            "Exactly"
+        } else {
+            "About"
+        },
+        files_maybe_plural = if files == 1 { "file" } else { "files" },
         directories_maybe_plural = if total_dirs == 1 {
             "directory"
         } else {
@@ -296,6 +1116,14 @@ fn print_configuration_info(
         } else {
             "directories"
         },
+        empty_dirs_info = if empty_dirs > 0. {
+            format!(
+                " Approximately {}% of leaf directories will be left empty.",
+                (empty_dirs * 100.).round()
+            )
+        } else {
+            String::new()
+        },
         bytes_info = if bytes > 0 {
             format!(
                 " Each file will contain approximately {} {bytes_maybe_plural} of random \
@@ -325,36 +1153,66 @@ fn print_configuration_info(
     .attach(ExitCode::from(sysexits::ExitCode::IoErr))
 }
 
-#[cfg_attr(
-    feature = "tracing",
-    tracing::instrument(level = "trace", skip(output))
-)]
-fn print_stats(GeneratorStats { files, dirs, bytes }: GeneratorStats, output: &mut impl Write) {
-    // Ignore I/O errors since it'd be dumb to fail if we actually succeeded in
-    // creating all the files
-    let _ = writeln!(
-        output,
-        "Created {} {files_maybe_plural}{bytes_info} across {} {directories_maybe_plural}.",
-        files.separate_with_commas(),
-        dirs.separate_with_commas(),
-        files_maybe_plural = if files == 1 { "file" } else { "files" },
-        directories_maybe_plural = if dirs == 1 {
-            "directory"
-        } else {
-            "directories"
-        },
-        bytes_info = if bytes > 0 {
-            log!(Level::Info, "{bytes} bytes written");
-            format!(" ({})", ByteSize(bytes).display().si())
-        } else {
-            String::new()
-        }
-    );
+/// Fails with [`Error::Cancelled`] if the run was stopped early, attaching the
+/// partial report.
+fn completed(report: GeneratorReport) -> Result<GeneratorReport, Error> {
+    if !report.stopped_early {
+        return Ok(report);
+    }
+    Err(Report::new(Error::Cancelled))
+        .attach(report)
+        .attach(ExitCode::from(sysexits::ExitCode::TempFail))
+}
+
+fn generate_report(config: Configuration) -> Result<GeneratorReport, Error> {
+    let root_dir = config.root_dir.clone();
+    let (seed, dry_run, print_digest) = (config.seed, config.dry_run, config.digest);
+    let archived = config.archive.is_some();
+    let throttle = config.throttle.clone();
+    let sync = config.sync.clone();
+
+    let start = Instant::now();
+    let (stats, phases) = run_generator(config)?;
+    let GeneratorStats {
+        files,
+        dirs,
+        bytes,
+        reused_files,
+        empty_files,
+        digest,
+        stopped_early,
+    } = stats;
+    let elapsed = start.elapsed();
+
+    if bytes > 0 {
+        log!(Level::Info, "{bytes} bytes written");
+    }
+    Ok(GeneratorReport {
+        root_dir,
+        seed,
+        files,
+        dirs: dirs as u64,
+        bytes,
+        empty_files,
+        reused_files,
+        digest: print_digest.then_some(digest),
+        dry_run,
+        archived,
+        stopped_early,
+        rates: throttle.map(|throttle| throttle.rates(elapsed)),
+        sync_elapsed: sync.map(|sync| sync.elapsed()),
+        phases,
+        elapsed,
+    })
 }
 
 #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace"))]
-fn run_generator(config: Configuration) -> Result<GeneratorStats, Error> {
-    let parallelism = thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap());
+fn run_generator(config: Configuration) -> Result<(GeneratorStats, Phases), Error> {
+    let start = Instant::now();
+    let jobs = config.jobs.unwrap_or_else(|| {
+        thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap())
+    });
+    let queue_depth = config.queue_depth.unwrap_or_else(|| jobs.saturating_pow(2));
     let mut runtime = tokio::runtime::Builder::new_current_thread();
     #[cfg(all(not(miri), target_os = "linux"))]
     runtime.on_thread_start(|| {
@@ -366,13 +1224,13 @@ fn run_generator(config: Configuration) -> Result<GeneratorStats, Error> {
         let _ = result;
     });
     let runtime = runtime
-        .max_blocking_threads(parallelism.get())
+        .max_blocking_threads(jobs.get())
         .build()
         .change_context(Error::RuntimeCreation)
         .attach(ExitCode::from(sysexits::ExitCode::OsErr))?;
 
     log!(Level::Info, "Starting config: {config:?}");
-    runtime.block_on(run_generator_async(config, parallelism))
+    runtime.block_on(run_generator_async(config, queue_depth, start))
 }
 
 #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace"))]
@@ -387,29 +1245,111 @@ async fn run_generator_async(
         dirs_per_dir,
         bytes_per_file,
         max_depth,
+        flat,
+        empty_dirs,
+        resume,
+        manifest,
+        archive,
+        backend,
+        dry_run,
+        digest,
+        progress,
+        observer,
+        cancellation,
+        time_limit,
+        jobs: _,
+        queue_depth: _,
+        throttle,
+        sync,
+        journal,
+        verifier,
+        planner,
         seed,
-        human_info: _,
+        namespace: _,
+        human_info: HumanInfo { total_dirs, .. },
     }: Configuration,
-    parallelism: NonZeroUsize,
-) -> Result<GeneratorStats, Error> {
+    queue_depth: NonZeroUsize,
+    start: Instant,
+) -> Result<(GeneratorStats, Phases), Error> {
+    let stop = StopCondition {
+        token: cancellation,
+        deadline: time_limit.and_then(|limit| Instant::now().checked_add(limit)),
+    };
+    let progress = progress.then(|| {
+        Progress::new(ProgressTarget {
+            files,
+            dirs: total_dirs as u64,
+            // Skipped tasks only add their bytes once the run completes
+            bytes: bytes.saturating_sub(journal.as_ref().map_or(0, |j| j.skipped().bytes)),
+        })
+    });
+
     macro_rules! run {
         ($generator:expr) => {{
-            run(
-                root_dir,
-                files,
-                dirs_per_dir,
-                max_depth.try_into().unwrap_or(usize::MAX),
-                parallelism,
-                $generator,
-            )
-            .await
+            if flat {
+                run_flat(root_dir, files, queue_depth, $generator, progress, &stop).await
+            } else {
+                run(
+                    root_dir,
+                    files,
+                    dirs_per_dir,
+                    max_depth.try_into().unwrap_or(usize::MAX),
+                    queue_depth,
+                    $generator,
+                    progress,
+                    &stop,
+                )
+                .await
+            }
         }};
     }
 
+    let manifest = manifest
+        .map(|(path, format)| {
+            Manifest::create(&path, format, root_dir.clone())
+                .map(Arc::new)
+                .attach_printable_lazy(|| format!("Failed to create manifest {path:?}"))
+                .change_context(Error::InvalidEnvironment)
+                .attach(ExitCode::from(sysexits::ExitCode::CantCreat))
+        })
+        .transpose()?;
+    let archive = archive
+        .map(|(output, format)| {
+            Archive::create(&output, format, root_dir.clone())
+                .map(Arc::new)
+                .attach_printable_lazy(|| format!("Failed to create archive {output:?}"))
+                .change_context(Error::InvalidEnvironment)
+                .attach(ExitCode::from(sysexits::ExitCode::CantCreat))
+        })
+        .transpose()?;
+
+    let backend = backend.unwrap_or_else(|| Arc::new(Filesystem));
+    let backend: Arc<dyn Backend> = match throttle {
+        Some(throttle) => Arc::new(Throttled { backend, throttle }),
+        None => backend,
+    };
+
     let bytes = NonZeroU64::new(bytes);
     let dynamic = DynamicGenerator {
         num_dirs_distr: truncatable_normal(dirs_per_dir),
         random: Xoshiro256PlusPlus::seed_from_u64(seed),
+        empty_dirs: (empty_dirs > 0.).then(|| Bernoulli::new(empty_dirs).unwrap()),
+        mode: match (verifier, planner, &archive) {
+            (Some(verifier), _, _) => TaskMode::Verify(verifier),
+            (None, Some(planner), _) => TaskMode::Plan { planner, ticket: 0 },
+            (None, None, Some(archive)) => TaskMode::Archive {
+                archive: archive.clone(),
+                ticket: 0,
+            },
+            (None, None, None) if dry_run => TaskMode::DryRun,
+            (None, None, None) if resume => TaskMode::Resume(backend.clone()),
+            (None, None, None) => TaskMode::Create(backend.clone()),
+        },
+        manifest: manifest.clone(),
+        digest: digest.then(|| Arc::new(TreeDigest::new(root_dir.clone()))),
+        observer,
+        sync: sync.clone(),
+        journal: journal.clone(),
 
         bytes: bytes.map(|_| GeneratorBytes {
             num_bytes_distr: truncatable_normal(bytes_per_file),
@@ -417,13 +1357,220 @@ async fn run_generator_async(
         }),
     };
 
-    if files_exact || (bytes_exact && bytes.is_some()) {
-        run!(StaticGenerator::new(
-            dynamic,
-            files_exact.then_some(files),
-            bytes_exact.then_some(bytes).flatten(),
-        ))
+    let sync_root = sync.as_ref().map(|_| root_dir.clone());
+    let files_exact = files_exact.then(|| NonZeroU64::new(files)).flatten();
+    let bytes_exact = bytes_exact.then_some(bytes).flatten();
+    let generation_start = Instant::now();
+    let stats = if files_exact.is_some() || bytes_exact.is_some() {
+        run!(StaticGenerator::new(dynamic, files_exact, bytes_exact))
     } else {
         run!(dynamic)
+    };
+    let finish_start = Instant::now();
+    let stats = if let Some(journal) = journal {
+        journal
+            .finish(matches!(stats, Ok(GeneratorStats { stopped_early, .. }) if !stopped_early))
+            .attach_printable("Failed to write to journal")
+            .change_context(Error::Io)
+            .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+        stats.map(|mut stats| {
+            let Checkpoint {
+                tasks: _,
+                bytes,
+                empty_files,
+                digest,
+            } = journal.skipped();
+            stats.bytes += bytes;
+            stats.empty_files += empty_files;
+            stats.digest = stats.digest.wrapping_add(digest);
+            stats
+        })
+    } else {
+        stats
+    }?;
+
+    if let (Some(sync), Some(root_dir)) = (sync, sync_root) {
+        sync.all(&*backend, &root_dir)
+            .attach_printable_lazy(|| format!("Failed to sync the filesystem at {root_dir:?}"))
+            .change_context(Error::Io)
+            .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+    }
+    if let Some(manifest) = manifest {
+        manifest
+            .flush()
+            .attach_printable("Failed to write to manifest")
+            .change_context(Error::Io)
+            .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+    }
+    if let Some(archive) = archive {
+        Arc::into_inner(archive)
+            .expect("All tasks have completed")
+            .finish()
+            .attach_printable("Failed to write to archive")
+            .change_context(Error::Io)
+            .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+    }
+    Ok((stats, Phases {
+        setup: generation_start - start,
+        generation: finish_start - generation_start,
+        finish: finish_start.elapsed(),
+    }))
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(discrepancies, output))
+)]
+fn print_verification(
+    GeneratorStats {
+        files,
+        dirs,
+        bytes,
+        reused_files: _,
+        empty_files: _,
+        digest: _,
+        stopped_early: _,
+    }: GeneratorStats,
+    discrepancies: &[(PathBuf, Discrepancy)],
+    output: &mut impl Write,
+) -> Result<(), Error> {
+    let mut counts = [0u64; 4];
+    for (path, discrepancy) in discrepancies {
+        counts[*discrepancy as usize] += 1;
+        let _ = writeln!(output, "{discrepancy}: {}", path.display());
+    }
+
+    let _ = writeln!(
+        output,
+        "Verified {} {files_maybe_plural}{bytes_info} across {} {directories_maybe_plural}.",
+        files.separate_with_commas(),
+        dirs.separate_with_commas(),
+        files_maybe_plural = if files == 1 { "file" } else { "files" },
+        directories_maybe_plural = if dirs == 1 {
+            "directory"
+        } else {
+            "directories"
+        },
+        bytes_info = if bytes > 0 {
+            format!(" ({})", ByteSize(bytes).display().si())
+        } else {
+            String::new()
+        },
+    );
+
+    if discrepancies.is_empty() {
+        return Ok(());
     }
+    let [missing, extra, resized, mismatched] = counts.map(|n| n.separate_with_commas());
+    Err(Report::new(Error::VerificationFailed))
+        .attach_printable(format!(
+            "Found {missing} missing, {extra} extra, {resized} resized, and {mismatched} \
+             content-mismatched entries."
+        ))
+        .attach(ExitCode::from(sysexits::ExitCode::DataErr))
+}
+
+fn configuration_json(
+    &Configuration {
+        ref root_dir,
+        files,
+        bytes,
+        files_exact,
+        bytes_exact,
+        fill_byte,
+        dirs_per_dir,
+        bytes_per_file,
+        max_depth,
+        flat,
+        empty_dirs,
+        resume,
+        manifest: _,
+        archive: _,
+        backend: _,
+        dry_run,
+        digest: _,
+        progress: _,
+        observer: _,
+        cancellation: _,
+        time_limit: _,
+        jobs: _,
+        queue_depth: _,
+        throttle: _,
+        sync: _,
+        journal: _,
+        verifier: _,
+        planner: _,
+        seed,
+        namespace: _,
+        human_info:
+            HumanInfo {
+                dirs_per_dir: _,
+                total_dirs,
+                bytes_per_files: _,
+            },
+    }: &Configuration,
+) -> String {
+    let mut json = String::from("{\"root_dir\":");
+    push_json_string(&mut json, &root_dir.to_string_lossy());
+    let _ = write!(
+        json,
+        ",\"files\":{files},\"files_exact\":{files_exact},\"bytes\":{bytes},\"bytes_exact\":\
+         {bytes_exact},\"fill_byte\":{},\"max_depth\":{max_depth},\"flat\":{flat},\"dirs_per_dir\"\
+         :{dirs_per_dir},\"total_dirs\":{total_dirs},\"bytes_per_file\":{bytes_per_file},\"\
+         empty_dirs\":{empty_dirs},\"resume\":{resume},\"dry_run\":{dry_run},\"seed\":{seed}}}",
+        fill_byte.map_or_else(|| "null".to_string(), |b| b.to_string()),
+    );
+    json
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(output))
+)]
+fn print_json(
+    configuration: &str,
+    &GeneratorReport {
+        root_dir: _,
+        seed: _,
+        files,
+        dirs,
+        bytes,
+        empty_files,
+        reused_files,
+        digest,
+        dry_run: _,
+        archived: _,
+        stopped_early,
+        rates,
+        sync_elapsed,
+        phases: _,
+        elapsed,
+    }: &GeneratorReport,
+    output: &mut impl Write,
+) -> Result<(), Error> {
+    writeln!(
+        output,
+        "{{\"configuration\":{configuration},\"stats\":{{\"files\":{files},\"dirs\":{dirs},\"\
+         bytes\":{bytes},\"empty_files\":{empty_files},\"reused_files\":{reused_files},\"digest\":\
+         {}}},\"stopped_early\":{stopped_early},\"rates\":{},\"sync_secs\":{},\"elapsed_secs\":\
+         {}}}",
+        digest.map_or_else(|| "null".to_string(), |digest| format!("\"{digest:016x}\"")),
+        rates.map_or_else(
+            || "null".to_string(),
+            |Rates {
+                 files_per_sec,
+                 bytes_per_sec,
+             }| {
+                format!("{{\"files_per_sec\":{files_per_sec},\"bytes_per_sec\":{bytes_per_sec}}}")
+            }
+        ),
+        sync_elapsed.map_or_else(
+            || "null".to_string(),
+            |sync_elapsed| sync_elapsed.as_secs_f64().to_string()
+        ),
+        elapsed.as_secs_f64(),
+    )
+    .attach_printable("Failed to write to output stream")
+    .change_context(Error::Io)
+    .attach(ExitCode::from(sysexits::ExitCode::IoErr))
 }
@@ -1,5 +1,9 @@
 pub use generator::*;
+pub use mutator::*;
+pub use series::*;
 
 mod core;
 mod generator;
+mod mutator;
+mod series;
 mod utils;
@@ -1,17 +1,22 @@
 use std::{
     borrow::Cow,
+    fmt::Display,
     io,
-    io::{Write, stdout},
-    num::NonZeroU64,
-    path::PathBuf,
+    io::{Write, stderr, stdout},
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
     process::{ExitCode, Termination},
+    time::Duration,
 };
 
-use clap::{ArgAction, Args, Parser, ValueHint, builder::ArgPredicate};
+use clap::{
+    ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum, ValueHint,
+    builder::ArgPredicate, error::ErrorKind,
+};
 use clap_num::si_number;
 use clap_verbosity_flag::Verbosity;
 use error_stack::ResultExt;
-use ftzz::{Generator, NumFilesWithRatio, NumFilesWithRatioError};
+use ftzz::{Churn, Generator, Mutator, NumFilesWithRatio, NumFilesWithRatioError};
 use io_adapters::WriteExtension;
 
 #[cfg(not(feature = "trace"))]
@@ -35,13 +40,17 @@ type DefaultLevel = clap_verbosity_flag::TraceLevel;
 /// the file contents with the `total-bytes` option.
 #[derive(Parser, Debug)]
 #[command(version, author = "Alex Saveau (@SUPERCILEX)")]
-#[command(infer_subcommands = true, infer_long_args = true)]
+#[command(infer_long_args = true)]
+#[command(args_conflicts_with_subcommands = true)]
 #[command(disable_help_flag = true)]
 #[command(max_term_width = 100)]
 #[cfg_attr(test, command(help_expected = true))]
 struct Ftzz {
+    #[command(subcommand)]
+    cmd: Option<Cmd>,
+
     #[command(flatten)]
-    options: Generate,
+    options: Option<Generate>,
 
     #[command(flatten)]
     #[command(next_display_order = None)]
@@ -53,6 +62,32 @@ struct Ftzz {
     help: Option<bool>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// Apply reproducible changes to an existing tree
+    ///
+    /// Each pre-existing file is changed at most once in one of the specified
+    /// ways after which new files are added. Given the same tree, churn
+    /// fractions, and seed, the same changes will always be applied.
+    Mutate(Mutate),
+
+    /// Generate a series of snapshots where each one is derived from the last
+    ///
+    /// The first snapshot is generated as usual after which each subsequent
+    /// snapshot is a copy of its predecessor with churn applied. Snapshots are
+    /// named `snap-0` through `snap-{K-1}` and placed in the root directory.
+    Series(Series),
+
+    /// Check that an existing tree matches the one its parameters would
+    /// generate
+    ///
+    /// The expected tree is derived from the same parameters and seed used to
+    /// generate it without writing anything to disk. Missing, extra, resized,
+    /// and content-mismatched entries are reported after which the command
+    /// fails if any were found.
+    Verify(Generate),
+}
+
 #[derive(Args, Debug)]
 #[command(arg_required_else_help = true)]
 struct Generate {
@@ -67,8 +102,15 @@ struct Generate {
     /// Note: this value is probabilistically respected, meaning any number of
     /// files may be generated so long as we attempt to get close to N.
     #[arg(short = 'n', long = "files", alias = "num-files")]
+    #[arg(required_unless_present = "num_dirs")]
     #[arg(value_parser = num_files_parser)]
-    num_files: NonZeroU64,
+    num_files: Option<NonZeroU64>,
+
+    /// Generate approximately N directories without any files
+    #[arg(long = "dirs", alias = "num-dirs")]
+    #[arg(conflicts_with_all = & ["num_files", "num-bytes", "files_exact", "file_to_dir_ratio", "flat"])]
+    #[arg(value_parser = num_dirs_parser)]
+    num_dirs: Option<NonZeroU64>,
 
     /// Whether or not to generate exactly N files
     #[arg(long = "files-exact")]
@@ -119,12 +161,166 @@ struct Generate {
     #[arg(value_parser = file_to_dir_ratio_parser)]
     file_to_dir_ratio: Option<NonZeroU64>,
 
+    /// Generate all files in a single directory
+    ///
+    /// Unlike `--max-depth 0`, file creation is split across parallel tasks
+    /// which makes this mode suitable for generating directories with millions
+    /// of entries.
+    #[arg(long = "flat")]
+    #[arg(conflicts_with_all = & ["max_depth", "file_to_dir_ratio"])]
+    flat: bool,
+
+    /// The fraction of leaf directories to leave empty
+    ///
+    /// Note: files that would have been generated in these directories are
+    /// not redistributed unless an exact number of files was requested.
+    #[arg(long = "empty-dirs", value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    empty_dirs: f64,
+
+    /// Allow generating files in a non-empty root directory
+    ///
+    /// To avoid conflicting with pre-existing entries, files will be generated
+    /// in a new subdirectory of the root directory named after the seed.
+    #[arg(long = "allow-non-empty")]
+    allow_non_empty: bool,
+
+    /// Complete a previously interrupted generation
+    ///
+    /// Generation must be restarted with the same parameters. If the
+    /// interrupted run was also started with `--resume`, directories recorded
+    /// as complete in the `.ftzz-journal` it left behind are skipped.
+    /// Elsewhere, files which already exist with their expected size are
+    /// reused while missing or truncated files are regenerated.
+    #[arg(long)]
+    resume: bool,
+
+    /// Write a record for each generated file and directory to this file
+    ///
+    /// Records contain the entry's path relative to the root directory, its
+    /// type, size, permissions, and the FNV-1a hash of its contents.
+    #[arg(long, value_name = "FILE")]
+    #[arg(value_hint = ValueHint::FilePath)]
+    manifest: Option<PathBuf>,
+
+    /// The manifest's format
+    ///
+    /// Defaults to CSV if the manifest's file extension is `.csv` and JSON
+    /// Lines otherwise.
+    #[arg(long, value_name = "FORMAT", requires = "manifest")]
+    manifest_format: Option<ManifestFormat>,
+
+    /// Stream the tree into this archive instead of writing it to disk
+    ///
+    /// Entries are named after the root directory and appear in the same
+    /// order on every run. Use `-` to write the archive to stdout.
+    #[arg(long, value_name = "FILE")]
+    #[arg(value_hint = ValueHint::FilePath)]
+    #[arg(conflicts_with_all = & ["resume", "dry_run"])]
+    archive: Option<PathBuf>,
+
+    /// The archive's format
+    ///
+    /// Defaults to zip or cpio if the archive's file extension is `.zip` or
+    /// `.cpio` respectively and tar otherwise.
+    #[arg(long, value_name = "FORMAT", requires = "archive")]
+    archive_format: Option<ArchiveFormat>,
+
+    /// How the contents of zip entries are stored
+    ///
+    /// Only applies to zip archives. Defaults to stored.
+    #[arg(long, value_name = "METHOD", requires = "archive")]
+    zip_compression: Option<ZipCompression>,
+
+    /// Print what would be generated without writing anything to disk
+    ///
+    /// The tree is planned exactly as it would be during a real run, so the
+    /// reported file, directory, and byte counts match those of a real run.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Report a digest of the generated tree
+    ///
+    /// The digest is derived from every entry's path and contents but not the
+    /// order in which entries were created, so two trees can be compared by
+    /// comparing their digests.
+    #[arg(long)]
+    digest: bool,
+
+    /// How to report the configuration and results of the run
+    ///
+    /// The JSON output contains the resolved configuration (including the
+    /// derived seed), the final statistics, and the elapsed time.
+    #[arg(long, value_name = "FORMAT", default_value = "human")]
+    output: OutputFormat,
+
+    /// Don't report progress while generating
+    ///
+    /// Progress is drawn as a bar when stderr is a terminal and logged
+    /// periodically otherwise (visible with `-v`).
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Stop queueing new work after this much time has elapsed
+    ///
+    /// In-flight work is completed before exiting with a partial tree which
+    /// can be completed with `--resume`. Accepts a number of seconds or a
+    /// number suffixed with `ms`, `s`, `m`, or `h`.
+    #[arg(long, value_name = "DURATION")]
+    #[arg(value_parser = duration_parser)]
+    time_limit: Option<Duration>,
+
+    /// The number of worker threads creating files and directories
+    ///
+    /// Defaults to the number of available cores. The generated tree is the
+    /// same regardless of the number of jobs.
+    #[arg(short = 'j', long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// The number of tasks to queue before waiting for the oldest ones to
+    /// complete
+    ///
+    /// Defaults to the square of the number of jobs. Slow network filesystems
+    /// may benefit from a deeper queue.
+    #[arg(long, value_name = "N")]
+    queue_depth: Option<NonZeroUsize>,
+
+    /// Write at most N files per second
+    ///
+    /// The limit applies across all jobs. The achieved rate is included in the
+    /// final report.
+    #[arg(long, value_name = "N")]
+    #[arg(value_parser = rate_parser, conflicts_with = "dry_run")]
+    max_files_per_sec: Option<NonZeroU64>,
+
+    /// Write at most N bytes per second
+    ///
+    /// The limit applies across all jobs. The achieved rate is included in the
+    /// final report.
+    #[arg(long, value_name = "N")]
+    #[arg(value_parser = rate_parser, conflicts_with = "dry_run")]
+    max_bytes_per_sec: Option<NonZeroU64>,
+
+    /// When to flush created entries to durable storage
+    ///
+    /// The time spent syncing is included in the final report.
+    #[arg(long, value_name = "POLICY", default_value = "none")]
+    sync: SyncPolicy,
+
     /// Change the PRNG's starting seed
     ///
     /// For example, you can use bash's `$RANDOM` function.
     #[arg(long = "seed", alias = "entropy")]
     #[arg(default_value = "0")]
     seed: u64,
+
+    /// How the tree's seed is derived from its parameters
+    ///
+    /// Use version 1 to reproduce trees generated by ftzz 4.0 and earlier
+    /// (which may differ between builds using different Rust releases).
+    #[arg(long, value_name = "VERSION", default_value = "2")]
+    seed_version: SeedVersion,
 }
 
 impl TryFrom<Generate> for Generator {
@@ -134,6 +330,7 @@ impl TryFrom<Generate> for Generator {
         Generate {
             root_dir,
             num_files,
+            num_dirs,
             files_exact,
             num_bytes,
             fill_byte,
@@ -141,7 +338,27 @@ impl TryFrom<Generate> for Generator {
             exact: _,
             max_depth,
             file_to_dir_ratio,
+            flat,
+            empty_dirs,
+            allow_non_empty,
+            resume,
+            manifest,
+            manifest_format,
+            archive,
+            archive_format,
+            zip_compression,
+            dry_run,
+            digest,
+            output,
+            no_progress,
+            time_limit,
+            jobs,
+            queue_depth,
+            max_files_per_sec,
+            max_bytes_per_sec,
+            sync,
             seed,
+            seed_version,
         }: Generate,
     ) -> Result<Self, Self::Error> {
         let builder = Self::builder();
@@ -150,17 +367,182 @@ impl TryFrom<Generate> for Generator {
         let builder = builder.num_bytes(num_bytes);
         let builder = builder.bytes_exact(bytes_exact);
         let builder = builder.max_depth(max_depth);
+        let builder = builder.flat(flat);
+        let builder = builder.empty_dirs(empty_dirs);
+        let builder = builder.allow_non_empty(allow_non_empty);
+        let builder = builder.resume(resume);
+        let builder = builder.manifest_format(
+            match manifest_format {
+                Some(format) => format,
+                None if manifest
+                    .as_ref()
+                    .and_then(|path| path.extension())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) =>
+                {
+                    ManifestFormat::Csv
+                }
+                None => ManifestFormat::Jsonl,
+            }
+            .into(),
+        );
+        let builder = builder.maybe_manifest(manifest);
+        let builder = builder.archive_format(
+            match resolve_archive_format(archive_format, archive.as_deref()) {
+                ArchiveFormat::Tar => ftzz::ArchiveFormat::Tar,
+                ArchiveFormat::Zip => ftzz::ArchiveFormat::Zip(
+                    zip_compression.unwrap_or(ZipCompression::Stored).into(),
+                ),
+                ArchiveFormat::Cpio => ftzz::ArchiveFormat::Cpio,
+            },
+        );
+        let builder = builder.maybe_archive(archive.map(|path| {
+            if path == Path::new("-") {
+                ftzz::ArchiveOutput::Stdout
+            } else {
+                path.into()
+            }
+        }));
+        let builder = builder.dry_run(dry_run);
+        let builder = builder.digest(digest);
+        let builder = builder.progress(!no_progress);
+        let builder = builder.maybe_time_limit(time_limit);
+        let builder = builder.maybe_jobs(jobs);
+        let builder = builder.maybe_queue_depth(queue_depth);
+        let builder = builder.maybe_max_files_per_sec(max_files_per_sec);
+        let builder = builder.maybe_max_bytes_per_sec(max_bytes_per_sec);
+        let builder = builder.sync(sync.into());
+        let builder = builder.output_format(output.into());
         let builder = builder.seed(seed);
+        let builder = builder.seed_version(seed_version.into());
         let builder = builder.maybe_fill_byte(fill_byte);
-        let builder = if let Some(ratio) = file_to_dir_ratio {
-            builder.num_files_with_ratio(NumFilesWithRatio::new(num_files, ratio)?)
-        } else {
-            builder.num_files_with_ratio(NumFilesWithRatio::from_num_files(num_files))
-        };
+        let builder =
+            builder.num_files_with_ratio(match (num_files, num_dirs, file_to_dir_ratio) {
+                (Some(num_files), _, Some(ratio)) => NumFilesWithRatio::new(num_files, ratio)?,
+                (Some(num_files), _, None) => NumFilesWithRatio::from_num_files(num_files),
+                (None, Some(num_dirs), _) => NumFilesWithRatio::dirs_only(num_dirs),
+                (None, None, _) => unreachable!("Either files or dirs are required"),
+            });
         Ok(builder.build())
     }
 }
 
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum ManifestFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+impl From<ManifestFormat> for ftzz::ManifestFormat {
+    fn from(format: ManifestFormat) -> Self {
+        match format {
+            ManifestFormat::Jsonl => Self::JsonLines,
+            ManifestFormat::Csv => Self::Csv,
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+enum ArchiveFormat {
+    /// A POSIX tar stream with GNU extensions for long paths
+    Tar,
+    /// A zip file with ZIP64 extensions for large archives
+    Zip,
+    /// A cpio stream in the newc format used by initramfs images
+    Cpio,
+}
+
+/// Defaults to the format matching the archive's file extension.
+fn resolve_archive_format(format: Option<ArchiveFormat>, archive: Option<&Path>) -> ArchiveFormat {
+    format.unwrap_or_else(|| {
+        match archive
+            .and_then(|path| path.extension())
+            .and_then(|ext| ext.to_str())
+        {
+            Some(ext) if ext.eq_ignore_ascii_case("zip") => ArchiveFormat::Zip,
+            Some(ext) if ext.eq_ignore_ascii_case("cpio") => ArchiveFormat::Cpio,
+            _ => ArchiveFormat::Tar,
+        }
+    })
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum ZipCompression {
+    /// Store contents uncompressed
+    Stored,
+    /// Compress contents with deflate
+    Deflated,
+}
+
+impl From<ZipCompression> for ftzz::ZipCompression {
+    fn from(compression: ZipCompression) -> Self {
+        match compression {
+            ZipCompression::Stored => Self::Stored,
+            ZipCompression::Deflated => Self::Deflated,
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum OutputFormat {
+    /// English sentences
+    Human,
+    /// A single JSON object
+    Json,
+}
+
+impl From<OutputFormat> for ftzz::OutputFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Human => Self::Human,
+            OutputFormat::Json => Self::Json,
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum SyncPolicy {
+    /// Leave flushing to the operating system
+    None,
+    /// Fsync each file after writing it
+    File,
+    /// Fsync each directory once its entries exist
+    Dir,
+    /// Sync the whole filesystem once the tree is complete
+    Fs,
+}
+
+impl From<SyncPolicy> for ftzz::SyncPolicy {
+    fn from(policy: SyncPolicy) -> Self {
+        match policy {
+            SyncPolicy::None => Self::None,
+            SyncPolicy::File => Self::File,
+            SyncPolicy::Dir => Self::Dir,
+            SyncPolicy::Fs => Self::Filesystem,
+        }
+    }
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+enum SeedVersion {
+    /// The standard library's default hasher
+    #[value(name = "1")]
+    V1,
+    /// FNV-1a, stable across releases
+    #[value(name = "2")]
+    V2,
+}
+
+impl From<SeedVersion> for ftzz::SeedVersion {
+    fn from(version: SeedVersion) -> Self {
+        match version {
+            SeedVersion::V1 => Self::V1,
+            SeedVersion::V2 => Self::V2,
+        }
+    }
+}
+
 #[cfg(test)]
 mod generate_tests {
     use super::*;
@@ -169,12 +551,33 @@ mod generate_tests {
     fn params_are_mapped_correctly() {
         let options = Generate {
             root_dir: PathBuf::from("abc"),
-            num_files: NonZeroU64::new(373).unwrap(),
+            num_files: Some(NonZeroU64::new(373).unwrap()),
+            num_dirs: None,
             num_bytes: 637,
             fill_byte: None,
             max_depth: 43,
             file_to_dir_ratio: Some(NonZeroU64::new(37).unwrap()),
+            flat: true,
+            empty_dirs: 0.25,
+            allow_non_empty: true,
+            resume: true,
+            manifest: Some(PathBuf::from("manifest.csv")),
+            manifest_format: None,
+            archive: Some(PathBuf::from("fixtures.zip")),
+            archive_format: None,
+            zip_compression: Some(ZipCompression::Deflated),
+            dry_run: true,
+            digest: true,
+            output: OutputFormat::Json,
+            no_progress: true,
+            time_limit: Some(Duration::from_secs(90)),
+            jobs: Some(NonZeroUsize::new(3).unwrap()),
+            queue_depth: Some(NonZeroUsize::new(64).unwrap()),
+            max_files_per_sec: Some(NonZeroU64::new(500).unwrap()),
+            max_bytes_per_sec: Some(NonZeroU64::new(1_000_000).unwrap()),
+            sync: SyncPolicy::Dir,
             seed: 775,
+            seed_version: SeedVersion::V1,
             files_exact: false,
             bytes_exact: false,
             exact: false,
@@ -188,7 +591,323 @@ mod generate_tests {
         assert!(hack.contains("num_bytes: 637"));
         assert!(hack.contains("max_depth: 43"));
         assert!(hack.contains("file_to_dir_ratio: 37"));
+        assert!(hack.contains("flat: true"));
+        assert!(hack.contains("empty_dirs: 0.25"));
+        assert!(hack.contains("allow_non_empty: true"));
+        assert!(hack.contains("resume: true"));
+        assert!(hack.contains("manifest: Some(\"manifest.csv\")"));
+        assert!(hack.contains("manifest_format: Csv"));
+        assert!(hack.contains("archive: Some(File(\"fixtures.zip\"))"));
+        assert!(hack.contains("archive_format: Zip(Deflated)"));
+        assert!(hack.contains("dry_run: true"));
+        assert!(hack.contains("digest: true"));
+        assert!(hack.contains("progress: false"));
+        assert!(hack.contains("time_limit: Some(90s)"));
+        assert!(hack.contains("jobs: Some(3)"));
+        assert!(hack.contains("queue_depth: Some(64)"));
+        assert!(hack.contains("max_files_per_sec: Some(500)"));
+        assert!(hack.contains("max_bytes_per_sec: Some(1000000)"));
+        assert!(hack.contains("sync: Dir"));
+        assert!(hack.contains("output_format: Json"));
+        assert!(hack.contains("seed: 775"));
+        assert!(hack.contains("seed_version: V1"));
+    }
+
+    #[test]
+    fn dirs_only_is_mapped_correctly() {
+        let options = Generate {
+            root_dir: PathBuf::from("abc"),
+            num_files: None,
+            num_dirs: Some(NonZeroU64::new(42).unwrap()),
+            num_bytes: 0,
+            fill_byte: None,
+            max_depth: 5,
+            file_to_dir_ratio: None,
+            flat: false,
+            empty_dirs: 0.,
+            allow_non_empty: false,
+            resume: false,
+            manifest: None,
+            manifest_format: None,
+            archive: None,
+            archive_format: None,
+            zip_compression: None,
+            dry_run: false,
+            digest: false,
+            output: OutputFormat::Human,
+            no_progress: false,
+            time_limit: None,
+            jobs: None,
+            queue_depth: None,
+            max_files_per_sec: None,
+            max_bytes_per_sec: None,
+            sync: SyncPolicy::None,
+            seed: 0,
+            seed_version: SeedVersion::V2,
+            files_exact: false,
+            bytes_exact: false,
+            exact: false,
+        };
+
+        let generator = Generator::try_from(options).unwrap();
+        let hack = format!("{generator:?}");
+
+        assert!(hack.contains("DirsOnly { num_dirs: 42 }"));
+    }
+}
+
+#[derive(Args, Debug)]
+#[command(arg_required_else_help = true)]
+struct Mutate {
+    /// The root directory of the tree to mutate
+    #[arg(value_hint = ValueHint::DirPath)]
+    root_dir: PathBuf,
+
+    #[command(flatten)]
+    churn: ChurnArgs,
+
+    /// Change the PRNG's starting seed
+    #[arg(long = "seed", alias = "entropy")]
+    #[arg(default_value = "0")]
+    seed: u64,
+}
+
+#[derive(Args, Debug)]
+#[command(arg_required_else_help = true)]
+struct Series {
+    #[command(flatten)]
+    generate: Generate,
+
+    /// The number of snapshots to generate
+    #[arg(short = 'k', long)]
+    #[arg(value_parser = num_snapshots_parser)]
+    #[arg(default_value = "10")]
+    snapshots: NonZeroU64,
+
+    #[command(flatten)]
+    churn: ChurnArgs,
+}
+
+#[derive(Args, Debug)]
+#[command(next_help_heading = "Churn")]
+struct ChurnArgs {
+    /// The fraction of files whose contents will be partially overwritten
+    #[arg(long, value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    modify: f64,
+
+    /// The fraction of files to which random data will be appended
+    #[arg(long, value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    append: f64,
+
+    /// The fraction of files which will be truncated
+    #[arg(long, value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    truncate: f64,
+
+    /// The fraction of files which will be deleted
+    #[arg(long, value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    delete: f64,
+
+    /// The fraction of files which will be renamed within their directory
+    #[arg(long, value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    rename: f64,
+
+    /// The fraction of files which will be moved to a different directory
+    #[arg(long = "move", value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    relocate: f64,
+
+    /// The number of new files to add relative to the number of existing files
+    #[arg(long, value_name = "FRACTION")]
+    #[arg(value_parser = fraction_parser)]
+    #[arg(default_value = "0")]
+    add: f64,
+}
+
+impl From<ChurnArgs> for Churn {
+    fn from(
+        ChurnArgs {
+            modify,
+            append,
+            truncate,
+            delete,
+            rename,
+            relocate,
+            add,
+        }: ChurnArgs,
+    ) -> Self {
+        Self::builder()
+            .modify(modify)
+            .append(append)
+            .truncate(truncate)
+            .delete(delete)
+            .rename(rename)
+            .relocate(relocate)
+            .add(add)
+            .build()
+    }
+}
+
+impl From<Mutate> for Mutator {
+    fn from(
+        Mutate {
+            root_dir,
+            churn,
+            seed,
+        }: Mutate,
+    ) -> Self {
+        Self::builder()
+            .root_dir(root_dir)
+            .churn(churn.into())
+            .seed(seed)
+            .build()
+    }
+}
+
+impl Series {
+    /// Returns the first argument given which only applies to a single tree
+    /// written to disk.
+    const fn single_tree_arg(&self) -> Option<&'static str> {
+        let Generate {
+            dry_run,
+            ref archive,
+            resume,
+            allow_non_empty,
+            ref manifest,
+            ..
+        } = self.generate;
+
+        if dry_run {
+            Some("--dry-run")
+        } else if archive.is_some() {
+            Some("--archive <FILE>")
+        } else if resume {
+            Some("--resume")
+        } else if allow_non_empty {
+            Some("--allow-non-empty")
+        } else if manifest.is_some() {
+            Some("--manifest <FILE>")
+        } else {
+            None
+        }
+    }
+}
+
+impl TryFrom<Series> for ftzz::Series {
+    type Error = NumFilesWithRatioError;
+
+    fn try_from(
+        Series {
+            generate,
+            snapshots,
+            churn,
+        }: Series,
+    ) -> Result<Self, Self::Error> {
+        Ok(Self::builder()
+            .generator(generate.try_into()?)
+            .snapshots(snapshots)
+            .churn(churn.into())
+            .build())
+    }
+}
+
+#[cfg(test)]
+mod mutate_tests {
+    use super::*;
+
+    fn churn() -> ChurnArgs {
+        ChurnArgs {
+            modify: 0.1,
+            append: 0.2,
+            truncate: 0.3,
+            delete: 0.05,
+            rename: 0.15,
+            relocate: 0.125,
+            add: 0.5,
+        }
+    }
+
+    #[test]
+    fn params_are_mapped_correctly() {
+        let options = Mutate {
+            root_dir: PathBuf::from("abc"),
+            churn: churn(),
+            seed: 775,
+        };
+
+        let mutator = Mutator::from(options);
+        let hack = format!("{mutator:?}");
+
+        assert!(hack.contains("root_dir: \"abc\""));
+        assert!(hack.contains("modify: 0.1"));
+        assert!(hack.contains("append: 0.2"));
+        assert!(hack.contains("truncate: 0.3"));
+        assert!(hack.contains("delete: 0.05"));
+        assert!(hack.contains("rename: 0.15"));
+        assert!(hack.contains("relocate: 0.125"));
+        assert!(hack.contains("add: 0.5"));
+        assert!(hack.contains("seed: 775"));
+    }
+
+    #[test]
+    fn series_params_are_mapped_correctly() {
+        let options = Series {
+            generate: Generate {
+                root_dir: PathBuf::from("abc"),
+                num_files: Some(NonZeroU64::new(373).unwrap()),
+                num_dirs: None,
+                num_bytes: 0,
+                fill_byte: None,
+                max_depth: 5,
+                file_to_dir_ratio: None,
+                flat: false,
+                empty_dirs: 0.,
+                allow_non_empty: false,
+                resume: false,
+                manifest: None,
+                manifest_format: None,
+                archive: None,
+                archive_format: None,
+                zip_compression: None,
+                dry_run: false,
+                digest: false,
+                output: OutputFormat::Human,
+                no_progress: false,
+                time_limit: None,
+                jobs: None,
+                queue_depth: None,
+                max_files_per_sec: None,
+                max_bytes_per_sec: None,
+                sync: SyncPolicy::None,
+                seed: 775,
+                seed_version: SeedVersion::V2,
+                files_exact: false,
+                bytes_exact: false,
+                exact: false,
+            },
+            snapshots: NonZeroU64::new(7).unwrap(),
+            churn: churn(),
+        };
+
+        let series = ftzz::Series::try_from(options).unwrap();
+        let hack = format!("{series:?}");
+
+        assert!(hack.contains("root_dir: \"abc\""));
+        assert!(hack.contains("num_files: 373"));
         assert!(hack.contains("seed: 775"));
+        assert!(hack.contains("snapshots: 7"));
+        assert!(hack.contains("relocate: 0.125"));
+        assert!(hack.contains("add: 0.5"));
     }
 }
 
@@ -196,6 +915,10 @@ mod generate_tests {
 pub enum CliError {
     #[error("File generator failed.")]
     Generator,
+    #[error("Tree mutator failed.")]
+    Mutator,
+    #[error("Tree verification failed.")]
+    Verifier,
     #[error("An argument combination was invalid.")]
     InvalidArgs,
 }
@@ -209,8 +932,33 @@ fn main() -> ExitCode {
     #[cfg(not(debug_assertions))]
     error_stack::Report::install_debug_hook::<std::panic::Location>(|_, _| {});
     error_stack::Report::install_debug_hook::<ExitCode>(|_, _| {});
+    error_stack::Report::install_debug_hook::<ftzz::GeneratorReport>(|_, _| {});
 
     let args = Ftzz::parse();
+    let (options, subcommand) = match &args.cmd {
+        Some(Cmd::Series(series)) => (Some(&series.generate), Some("series")),
+        Some(Cmd::Verify(options)) => (Some(options), Some("verify")),
+        Some(Cmd::Mutate(_)) => (None, None),
+        None => (args.options.as_ref(), None),
+    };
+    if let Some(options) = options
+        && options.zip_compression.is_some()
+        && resolve_archive_format(options.archive_format, options.archive.as_deref())
+            != ArchiveFormat::Zip
+    {
+        argument_conflict(
+            subcommand,
+            "the argument '--zip-compression' can only be used with zip archives",
+        );
+    }
+    if let Some(Cmd::Series(series)) = &args.cmd
+        && let Some(arg) = series.single_tree_arg()
+    {
+        argument_conflict(
+            subcommand,
+            format!("the argument '{arg}' cannot be used with the 'series' subcommand"),
+        );
+    }
 
     {
         let level = args.verbose.log_level().unwrap_or_else(log::Level::max);
@@ -262,32 +1010,103 @@ fn main() -> ExitCode {
     }
 }
 
+/// Exits with a usage error reported against the given subcommand or the
+/// top-level command.
+fn argument_conflict(subcommand: Option<&str>, message: impl Display) -> ! {
+    let mut command = Ftzz::command();
+    command.build();
+    let command = match subcommand {
+        Some(name) => command
+            .find_subcommand_mut(name)
+            .expect("The subcommand exists"),
+        None => &mut command,
+    };
+    command.error(ErrorKind::ArgumentConflict, message).exit()
+}
+
 fn ftzz(
     Ftzz {
+        cmd,
         options,
         verbose: _,
         help: _,
     }: Ftzz,
 ) -> error_stack::Result<(), CliError> {
     let stdout = stdout();
-    Generator::try_from(options)
-        .change_context(CliError::InvalidArgs)?
-        .generate(&mut stdout.write_adapter())
-        .change_context(CliError::Generator)
+    match (cmd, options) {
+        (Some(Cmd::Mutate(options)), _) => Mutator::from(options)
+            .mutate(&mut stdout.write_adapter())
+            .change_context(CliError::Mutator),
+        (Some(Cmd::Series(options)), _) => ftzz::Series::try_from(options)
+            .change_context(CliError::InvalidArgs)?
+            .generate(&mut stdout.write_adapter())
+            .change_context(CliError::Generator),
+        (Some(Cmd::Verify(options)), _) => Generator::try_from(options)
+            .change_context(CliError::InvalidArgs)?
+            .verify(&mut stdout.write_adapter())
+            .change_context(CliError::Verifier),
+        (None, Some(options)) => {
+            // Keep the archive's stream clean when it's written to stdout
+            let archive_on_stdout = options.archive.as_deref() == Some(Path::new("-"));
+            let generator = Generator::try_from(options).change_context(CliError::InvalidArgs)?;
+            if archive_on_stdout {
+                generator.generate(&mut stderr().write_adapter())
+            } else {
+                generator.generate(&mut stdout.write_adapter())
+            }
+            .map(drop)
+            .change_context(CliError::Generator)
+        }
+        (None, None) => unreachable!("Generator arguments are required without a subcommand"),
+    }
 }
 
 fn num_files_parser(s: &str) -> Result<NonZeroU64, Cow<'static, str>> {
     NonZeroU64::new(si_number(s)?).ok_or_else(|| "At least one file must be generated.".into())
 }
 
+fn num_dirs_parser(s: &str) -> Result<NonZeroU64, Cow<'static, str>> {
+    NonZeroU64::new(si_number(s)?).ok_or_else(|| "At least one directory must be generated.".into())
+}
+
+fn num_snapshots_parser(s: &str) -> Result<NonZeroU64, Cow<'static, str>> {
+    NonZeroU64::new(si_number(s)?).ok_or_else(|| "At least one snapshot must be generated.".into())
+}
+
+fn rate_parser(s: &str) -> Result<NonZeroU64, Cow<'static, str>> {
+    NonZeroU64::new(si_number(s)?).ok_or_else(|| "The rate must be positive.".into())
+}
+
 fn file_to_dir_ratio_parser(s: &str) -> Result<NonZeroU64, Cow<'static, str>> {
     NonZeroU64::new(si_number(s)?).ok_or_else(|| "Cannot have no files per directory.".into())
 }
 
+fn duration_parser(s: &str) -> Result<Duration, Cow<'static, str>> {
+    let (value, unit) = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map_or((s, "s"), |i| s.split_at(i));
+    let value = value.parse::<f64>().map_err(|e| e.to_string())?;
+    let secs = match unit {
+        "ms" => value / 1000.,
+        "s" => value,
+        "m" => value * 60.,
+        "h" => value * 3600.,
+        _ => return Err(format!("Unknown unit {unit:?}, expected ms, s, m, or h.").into()),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|e| e.to_string().into())
+}
+
+fn fraction_parser(s: &str) -> Result<f64, Cow<'static, str>> {
+    let fraction = s.parse::<f64>().map_err(|e| e.to_string())?;
+    if (0. ..=1.).contains(&fraction) {
+        Ok(fraction)
+    } else {
+        Err("Must be between 0 and 1.".into())
+    }
+}
+
 #[cfg(test)]
 mod cli_tests {
-    use clap::CommandFactory;
-
     use super::*;
 
     #[test]
@@ -299,4 +1118,21 @@ mod cli_tests {
     fn help_for_review() {
         supercilex_tests::help_for_review(Ftzz::command());
     }
+
+    #[test]
+    fn durations_are_parsed_with_units() {
+        assert_eq!(duration_parser("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(duration_parser("1.5s"), Ok(Duration::from_millis(1_500)));
+        assert_eq!(duration_parser("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(duration_parser("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(duration_parser("0.5h"), Ok(Duration::from_secs(1_800)));
+
+        assert_eq!(
+            duration_parser("1d"),
+            Err("Unknown unit \"d\", expected ms, s, m, or h.".into())
+        );
+        assert!(duration_parser("").is_err());
+        assert!(duration_parser("s").is_err());
+        assert!(duration_parser("1e400").is_err());
+    }
 }
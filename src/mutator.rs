@@ -0,0 +1,370 @@
+use std::{
+    cmp::max,
+    fmt::Write,
+    fs::{DirEntry, OpenOptions, remove_file, rename},
+    io,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bon::Builder;
+use error_stack::{Report, Result, ResultExt};
+use rand::{Rng, RngCore, SeedableRng, TryRngCore};
+use rand_xoshiro::Xoshiro256PlusPlus;
+use thousands::Separable;
+
+use crate::Error;
+
+/// The fraction of files affected by each type of change.
+///
+/// With the exception of [`add`](ChurnBuilder::add), each file undergoes at
+/// most one change which means the fractions may not sum to more than 1.
+#[derive(Debug, Copy, Clone, PartialEq, Builder)]
+pub struct Churn {
+    /// Overwrite part of a file's contents in place.
+    #[builder(default = 0.)]
+    modify: f64,
+    /// Append random data to the end of a file.
+    #[builder(default = 0.)]
+    append: f64,
+    /// Cut off the end of a file.
+    #[builder(default = 0.)]
+    truncate: f64,
+    #[builder(default = 0.)]
+    delete: f64,
+    /// Give a file a new name within its directory.
+    #[builder(default = 0.)]
+    rename: f64,
+    /// Move a file to a different directory.
+    #[builder(default = 0.)]
+    relocate: f64,
+    /// Create new files, relative to the number of pre-existing files.
+    #[builder(default = 0.)]
+    add: f64,
+}
+
+#[derive(Debug, Builder)]
+pub struct Mutator {
+    #[builder(into)]
+    root_dir: PathBuf,
+    churn: Churn,
+    #[builder(default = 0)]
+    seed: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimal_params_succeeds() {
+        let m = Mutator::builder()
+            .root_dir(PathBuf::from("abc"))
+            .churn(Churn::builder().build())
+            .build();
+
+        assert_eq!(m.root_dir, PathBuf::from("abc"));
+        assert_eq!(m.churn, Churn::builder().build());
+        assert_eq!(m.churn.modify, 0.);
+        assert_eq!(m.churn.add, 0.);
+        assert_eq!(m.seed, 0);
+    }
+}
+
+impl Mutator {
+    pub fn mutate(self, output: &mut impl Write) -> Result<(), Error> {
+        let Self {
+            root_dir,
+            churn,
+            seed,
+        } = self;
+
+        validate_churn(&churn)?;
+        let tree = Tree::scan(&root_dir)?;
+        print_stats(apply_churn(tree, churn, seed)?, output);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct MutationStats {
+    modified: u64,
+    appended: u64,
+    truncated: u64,
+    deleted: u64,
+    renamed: u64,
+    moved: u64,
+    added: u64,
+}
+
+/// A snapshot of the tree's files in a filesystem independent order.
+struct Tree {
+    dirs: Vec<PathBuf>,
+    /// The next unused file name in each directory.
+    next_file_names: Vec<u64>,
+    files: Vec<TreeFile>,
+    bytes: u64,
+}
+
+struct TreeFile {
+    dir: usize,
+    name: PathBuf,
+    len: u64,
+}
+
+impl Tree {
+    fn scan(root_dir: &Path) -> Result<Self, Error> {
+        let mut tree = Self {
+            dirs: vec![root_dir.to_path_buf()],
+            next_file_names: Vec::new(),
+            files: Vec::new(),
+            bytes: 0,
+        };
+
+        let mut dir = 0;
+        while let Some(path) = tree.dirs.get(dir) {
+            let mut entries = Vec::new();
+            for entry in path
+                .read_dir()
+                .attach_printable_lazy(|| format!("Failed to read directory {path:?}"))
+                .change_context(Error::InvalidEnvironment)
+                .attach(ExitCode::from(sysexits::ExitCode::NoInput))?
+            {
+                entries.push(
+                    entry
+                        .attach_printable_lazy(|| format!("Failed to read directory {path:?}"))
+                        .change_context(Error::Io)
+                        .attach(ExitCode::from(sysexits::ExitCode::IoErr))?,
+                );
+            }
+            entries.sort_by_key(DirEntry::file_name);
+
+            let mut next_file_name = 0;
+            for entry in entries {
+                let metadata = entry
+                    .metadata()
+                    .attach_printable_lazy(|| format!("Failed to stat {:?}", entry.path()))
+                    .change_context(Error::Io)
+                    .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+
+                if metadata.is_dir() {
+                    tree.dirs.push(entry.path());
+                } else if metadata.is_file() {
+                    if let Some(n) = entry
+                        .file_name()
+                        .to_str()
+                        .and_then(|s| s.parse::<u64>().ok())
+                    {
+                        next_file_name = max(next_file_name, n + 1);
+                    }
+                    tree.bytes += metadata.len();
+                    tree.files.push(TreeFile {
+                        dir,
+                        name: entry.file_name().into(),
+                        len: metadata.len(),
+                    });
+                }
+            }
+            tree.next_file_names.push(next_file_name);
+
+            dir += 1;
+        }
+
+        Ok(tree)
+    }
+
+    fn claim_file_name(&mut self, dir: usize) -> PathBuf {
+        let name = self.next_file_names[dir];
+        self.next_file_names[dir] += 1;
+        self.dirs[dir].join(name.to_string())
+    }
+}
+
+fn validate_churn(
+    &Churn {
+        modify,
+        append,
+        truncate,
+        delete,
+        rename,
+        relocate,
+        add,
+    }: &Churn,
+) -> Result<(), Error> {
+    let fractions = [modify, append, truncate, delete, rename, relocate, add];
+    if fractions.iter().any(|f| !(0. ..=1.).contains(f)) {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable("Churn fractions must be between 0 and 1.")
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    if modify + append + truncate + delete + rename + relocate > 1. {
+        return Err(Report::new(Error::InvalidConfiguration))
+            .attach_printable(
+                "The fractions of files to modify, append to, truncate, delete, rename, and move \
+                 cannot sum to more than 1.",
+            )
+            .attach(ExitCode::from(sysexits::ExitCode::Usage));
+    }
+    Ok(())
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip(tree)))]
+fn apply_churn(mut tree: Tree, churn: Churn, seed: u64) -> Result<MutationStats, Error> {
+    let Churn {
+        modify,
+        append,
+        truncate,
+        delete,
+        rename,
+        relocate,
+        add,
+    } = churn;
+
+    let mut random = Xoshiro256PlusPlus::seed_from_u64(seed);
+    let mut stats = MutationStats::default();
+
+    let num_files = tree.files.len() as u64;
+    // Sizes of new data are based on the average file size so that the tree's
+    // general shape is preserved
+    let max_bytes = 2 * max(tree.bytes.checked_div(num_files).unwrap_or(0), 1);
+
+    let files = std::mem::take(&mut tree.files);
+    for TreeFile { dir, name, len } in files {
+        let file = tree.dirs[dir].join(&name);
+        let mut action = random.random::<f64>();
+        let mut next = |fraction: f64| {
+            action -= fraction;
+            action < 0.
+        };
+
+        if next(modify) {
+            // Modifications happen in place, so empty files are left untouched
+            if len > 0 {
+                let offset = random.random_range(0..len);
+                let num_bytes = random.random_range(1..=len - offset);
+                write_random_bytes(&file, SeekFrom::Start(offset), num_bytes, &mut random)?;
+                stats.modified += 1;
+            }
+        } else if next(append) {
+            let num_bytes = random.random_range(1..=max_bytes);
+            write_random_bytes(&file, SeekFrom::End(0), num_bytes, &mut random)?;
+            stats.appended += 1;
+        } else if next(truncate) {
+            if len > 0 {
+                let new_len = random.random_range(0..len);
+                OpenOptions::new()
+                    .write(true)
+                    .open(&file)
+                    .and_then(|f| f.set_len(new_len))
+                    .attach_printable_lazy(|| format!("Failed to truncate file {file:?}"))
+                    .change_context(Error::Io)
+                    .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+                stats.truncated += 1;
+            }
+        } else if next(delete) {
+            remove_file(&file)
+                .attach_printable_lazy(|| format!("Failed to delete file {file:?}"))
+                .change_context(Error::Io)
+                .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+            stats.deleted += 1;
+        } else if next(rename) {
+            let to = tree.claim_file_name(dir);
+            move_file(&file, &to)?;
+            stats.renamed += 1;
+        } else if next(relocate) && tree.dirs.len() > 1 {
+            let mut target = random.random_range(0..tree.dirs.len() - 1);
+            if target >= dir {
+                target += 1;
+            }
+
+            let to = tree.claim_file_name(target);
+            move_file(&file, &to)?;
+            stats.moved += 1;
+        }
+    }
+
+    let num_new_files = (add * num_files as f64).round() as u64;
+    for _ in 0..num_new_files {
+        let dir = random.random_range(0..tree.dirs.len());
+        let file = tree.claim_file_name(dir);
+        let num_bytes = random.random_range(0..=max_bytes);
+        write_random_bytes(&file, SeekFrom::Start(0), num_bytes, &mut random)?;
+        stats.added += 1;
+    }
+
+    Ok(stats)
+}
+
+fn write_random_bytes(
+    file: &Path,
+    pos: SeekFrom,
+    num_bytes: u64,
+    random: &mut impl RngCore,
+) -> Result<(), Error> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(file)
+        .and_then(|mut f| {
+            f.seek(pos)?;
+            io::copy(&mut random.read_adapter().take(num_bytes), &mut f)
+        })
+        .attach_printable_lazy(|| format!("Failed to write to file {file:?}"))
+        .change_context(Error::Io)
+        .attach(ExitCode::from(sysexits::ExitCode::IoErr))?;
+    Ok(())
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<(), Error> {
+    rename(from, to)
+        .attach_printable_lazy(|| format!("Failed to move file {from:?} to {to:?}"))
+        .change_context(Error::Io)
+        .attach(ExitCode::from(sysexits::ExitCode::IoErr))
+}
+
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(output))
+)]
+fn print_stats(
+    MutationStats {
+        modified,
+        appended,
+        truncated,
+        deleted,
+        renamed,
+        moved,
+        added,
+    }: MutationStats,
+    output: &mut impl Write,
+) {
+    fn files(n: u64) -> String {
+        format!(
+            "{} {}",
+            n.separate_with_commas(),
+            if n == 1 { "file" } else { "files" }
+        )
+    }
+
+    // Ignore I/O errors since it'd be dumb to fail if we actually succeeded in
+    // mutating the tree
+    let _ = writeln!(
+        output,
+        "Modified {}, appended to {}, truncated {}, deleted {}, renamed {}, moved {}, and added \
+         {}.",
+        files(modified),
+        files(appended),
+        files(truncated),
+        files(deleted),
+        files(renamed),
+        files(moved),
+        files(added),
+    );
+}
@@ -1,5 +1,9 @@
 pub use fast_path::FastPathBuf;
 pub use file_names::*;
+pub use json::push_json_string;
+pub use seed_hasher::SeedHasher;
 
 mod fast_path;
 mod file_names;
+mod json;
+mod seed_hasher;
@@ -0,0 +1,28 @@
+/// Derives seeds from a tree's parameters with the 64-bit FNV-1a hash.
+///
+/// Unlike the standard library's hashers, its output is specified, so seeds
+/// derived from it stay the same across Rust releases.
+pub struct SeedHasher(u64);
+
+impl SeedHasher {
+    pub const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub const fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for SeedHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
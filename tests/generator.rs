@@ -2,25 +2,59 @@
 
 use std::{
     cmp::{max, min},
-    collections::VecDeque,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::Write,
-    fs::{DirEntry, File, create_dir},
+    fs,
+    fs::{DirEntry, File, OpenOptions, create_dir, remove_dir_all, remove_file},
     hash::{DefaultHasher, Hasher},
     io,
     io::{BufReader, Read, stdout},
-    num::NonZeroU64,
-    path::Path,
+    num::{NonZeroU64, NonZeroUsize},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
 };
 
 use expect_test::expect_file;
-use ftzz::{Generator, NumFilesWithRatio};
+use ftzz::{
+    ArchiveFormat, Backend, CancellationToken, Churn, CompletedTask, Entry, EntryKind,
+    EntryMetadata, Error, FileContents, Generator, GeneratorReport, ManifestFormat, MemoryBackend,
+    Mutator, NumFilesWithRatio, Observer, Phases, PlannedEntry, SeedVersion, Series, SyncPolicy,
+    ZipCompression,
+};
 use io_adapters::WriteExtension;
-use more_asserts::assert_le;
+use more_asserts::{assert_gt, assert_le, assert_lt};
 use rand::Rng;
 use rstest::rstest;
 
 use crate::inspect::InspectableTempDir;
 
+/// The journal kept in the root directory of incomplete trees.
+const JOURNAL: &str = ".ftzz-journal";
+
+/// Builds the 1,000 file tree which most tests compare across sinks.
+///
+/// A macro rather than a function as the builder's type changes with every
+/// field set.
+macro_rules! tree_builder {
+    ($root_dir:expr, $flat:expr, $bytes:expr, $files_exact:expr) => {{
+        let (num_bytes, bytes_exact): (u64, bool) = $bytes;
+        Generator::builder()
+            .root_dir($root_dir)
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(1_000).unwrap(),
+            ))
+            .num_bytes(num_bytes)
+            .files_exact($files_exact)
+            .bytes_exact(bytes_exact)
+            .flat($flat)
+    }};
+}
+
 mod inspect {
     use std::path::PathBuf;
 
@@ -64,6 +98,7 @@ fn gen_in_empty_existing_dir_is_allowed() {
         .num_files_with_ratio(NumFilesWithRatio::from_num_files(
             NonZeroU64::new(1).unwrap(),
         ))
+        .seed_version(SeedVersion::V1)
         .build()
         .generate(&mut golden)
         .unwrap();
@@ -97,6 +132,36 @@ fn gen_in_non_empty_existing_dir_is_disallowed() {
         .assert_eq(&golden);
 }
 
+#[test]
+fn gen_in_non_empty_existing_dir_is_allowed_with_opt_in() {
+    let dir = InspectableTempDir::new();
+    let mut golden = String::new();
+
+    let non_empty = dir.path.join("nonempty");
+    create_dir(&non_empty).unwrap();
+    File::create(non_empty.join("file")).unwrap();
+
+    for _ in 0..2 {
+        Generator::builder()
+            .root_dir(non_empty.clone())
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(1).unwrap(),
+            ))
+            .allow_non_empty(true)
+            .seed_version(SeedVersion::V1)
+            .build()
+            .generate(&mut golden)
+            .unwrap();
+    }
+    let mut golden = golden.replace(dir.path.to_str().unwrap(), "");
+    print_and_hash_dir(&dir.path, &mut golden);
+
+    expect_file![
+        "../testdata/generator/gen_in_non_empty_existing_dir_is_allowed_with_opt_in.stdout"
+    ]
+    .assert_eq(&golden);
+}
+
 #[test]
 fn gen_creates_new_dir_if_not_present() {
     let dir = InspectableTempDir::new();
@@ -107,6 +172,7 @@ fn gen_creates_new_dir_if_not_present() {
         .num_files_with_ratio(NumFilesWithRatio::from_num_files(
             NonZeroU64::new(1).unwrap(),
         ))
+        .seed_version(SeedVersion::V1)
         .build()
         .generate(&mut golden)
         .unwrap();
@@ -122,7 +188,10 @@ fn gen_creates_new_dir_if_not_present() {
 #[case(1_000)]
 #[cfg_attr(not(miri), case(10_000))]
 #[cfg_attr(not(miri), case(100_000))]
-fn simple_create_files(#[case] num_files: u64) {
+fn simple_create_files(
+    #[case] num_files: u64,
+    #[values(SeedVersion::V1, SeedVersion::V2)] seed_version: SeedVersion,
+) {
     let dir = InspectableTempDir::new();
     let mut golden = String::new();
 
@@ -131,6 +200,7 @@ fn simple_create_files(#[case] num_files: u64) {
         .num_files_with_ratio(NumFilesWithRatio::from_num_files(
             NonZeroU64::new(num_files).unwrap(),
         ))
+        .seed_version(seed_version)
         .build()
         .generate(&mut golden)
         .unwrap();
@@ -138,7 +208,12 @@ fn simple_create_files(#[case] num_files: u64) {
     print_and_hash_dir(&dir.path, &mut golden);
 
     expect_file![format!(
-        "../testdata/generator/simple_create_files_{num_files}.stdout"
+        "../testdata/generator/simple_create_files_{num_files}{}.stdout",
+        if seed_version == SeedVersion::V2 {
+            "_v2"
+        } else {
+            ""
+        },
     )]
     .assert_eq(&golden);
 }
@@ -175,6 +250,7 @@ fn advanced_create_files(
         .files_exact(files_exact)
         .bytes_exact(bytes.1)
         .max_depth(max_depth)
+        .seed_version(SeedVersion::V1)
         .build()
         .generate(&mut golden)
         .unwrap();
@@ -220,6 +296,7 @@ fn max_depth_is_respected(#[case] max_depth: u32) {
             NonZeroU64::new(10_000).unwrap(),
         ))
         .max_depth(max_depth)
+        .seed_version(SeedVersion::V1)
         .build()
         .generate(&mut golden)
         .unwrap();
@@ -234,179 +311,1927 @@ fn max_depth_is_respected(#[case] max_depth: u32) {
 }
 
 #[rstest]
-#[case(0)]
-#[case(42)]
-#[case(69)]
 #[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
-fn fill_byte_is_respected(#[case] fill_byte: u8) {
+fn flat_create_files(
+    #[values((0, false), (100_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+) {
     let dir = InspectableTempDir::new();
     let mut golden = String::new();
 
+    let num_files = 50_000;
     Generator::builder()
         .root_dir(dir.path.clone())
         .num_files_with_ratio(NumFilesWithRatio::from_num_files(
-            NonZeroU64::new(1_000).unwrap(),
+            NonZeroU64::new(num_files).unwrap(),
         ))
-        .num_bytes(100_000)
-        .fill_byte(fill_byte)
+        .num_bytes(bytes.0)
+        .files_exact(files_exact)
+        .bytes_exact(bytes.1)
+        .flat(true)
         .build()
         .generate(&mut golden)
         .unwrap();
 
-    let assert_fill_byte = || {
-        let mut queue = VecDeque::from([dir.path.clone()]);
-        while let Some(path) = queue.pop_front() {
-            for entry in path.read_dir().unwrap() {
-                let entry = entry.unwrap();
-                if entry.file_type().unwrap().is_dir() {
-                    queue.push_back(entry.path());
-                } else {
-                    for byte in BufReader::new(File::open(entry.path()).unwrap()).bytes() {
-                        assert_eq!(fill_byte, byte.unwrap());
-                    }
-                }
-            }
-        }
-    };
+    assert_eq!(find_max_depth(&dir.path), 0);
+    if files_exact {
+        assert_eq!(count_num_files(&dir.path), num_files);
+    }
+    if bytes.1 {
+        assert_eq!(count_num_bytes(&dir.path), bytes.0);
+    }
+    print_and_hash_dir(&dir.path, &mut golden);
 
-    assert_fill_byte();
+    expect_file![format!(
+        "../testdata/generator/flat_create_files{}{}{}.stdout",
+        if files_exact { "_exact" } else { "" },
+        if bytes.0 > 0 {
+            format!("_bytes_{}", bytes.0)
+        } else {
+            String::new()
+        },
+        if bytes.1 { "_exact" } else { "" },
+    )]
+    .assert_eq(&golden);
+}
+
+#[rstest]
+#[case(1)]
+#[case(1_000)]
+#[cfg_attr(not(miri), case(10_000))]
+fn dirs_only_create_dirs(
+    #[case] num_dirs: u64,
+    #[values(SeedVersion::V1, SeedVersion::V2)] seed_version: SeedVersion,
+) {
+    let dir = InspectableTempDir::new();
+    let mut golden = String::new();
+
+    Generator::builder()
+        .root_dir(dir.path.clone())
+        .num_files_with_ratio(NumFilesWithRatio::dirs_only(
+            NonZeroU64::new(num_dirs).unwrap(),
+        ))
+        .seed_version(seed_version)
+        .build()
+        .generate(&mut golden)
+        .unwrap();
+
+    assert_eq!(count_num_files(&dir.path), 0);
     print_and_hash_dir(&dir.path, &mut golden);
 
     expect_file![format!(
-        "../testdata/generator/fill_byte_is_respected_{fill_byte}.stdout"
+        "../testdata/generator/dirs_only_create_dirs_{num_dirs}{}.stdout",
+        if seed_version == SeedVersion::V2 {
+            "_v2"
+        } else {
+            ""
+        },
     )]
     .assert_eq(&golden);
 }
 
-#[test]
+#[rstest]
+#[case(0.5, false)]
+#[case(0.5, true)]
+#[case(1., true)]
 #[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
-fn fuzz_test() {
+fn empty_dirs_are_respected(#[case] empty_dirs: f64, #[case] files_exact: bool) {
     let dir = InspectableTempDir::new();
+    let mut golden = String::new();
 
-    let mut rng = rand::rng();
-    let num_files = rng.random_range(1..25_000);
-    let num_bytes = if rng.random() {
-        rng.random_range(0..100_000)
-    } else {
-        0
-    };
-    let max_depth = rng.random_range(0..100);
-    let ratio = rng.random_range(1..num_files);
-    let files_exact = rng.random();
-    let bytes_exact = rng.random();
-
-    let g = Generator::builder()
+    let num_files = 10_000;
+    Generator::builder()
         .root_dir(dir.path.clone())
-        .num_files_with_ratio(
-            NumFilesWithRatio::new(
-                NonZeroU64::new(num_files).unwrap(),
-                NonZeroU64::new(ratio).unwrap(),
-            )
-            .unwrap(),
-        )
-        .num_bytes(num_bytes)
-        .max_depth(max_depth)
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(num_files).unwrap(),
+        ))
         .files_exact(files_exact)
-        .bytes_exact(bytes_exact)
-        .build();
-    println!("Params: {g:?}");
-    g.generate(&mut stdout().write_adapter()).unwrap();
+        .empty_dirs(empty_dirs)
+        .seed_version(SeedVersion::V1)
+        .build()
+        .generate(&mut golden)
+        .unwrap();
 
-    assert_le!(find_max_depth(&dir.path), max_depth);
+    assert!(count_num_empty_dirs(&dir.path) > 0);
     if files_exact {
         assert_eq!(count_num_files(&dir.path), num_files);
     }
-    if bytes_exact {
-        assert_eq!(count_num_bytes(&dir.path), num_bytes);
-    }
+    print_and_hash_dir(&dir.path, &mut golden);
+
+    expect_file![format!(
+        "../testdata/generator/empty_dirs_are_respected{}_{empty_dirs}.stdout",
+        if files_exact { "_exact" } else { "" },
+    )]
+    .assert_eq(&golden);
 }
 
-/// Recursively hashes the file and directory names in dir
-fn print_and_hash_dir(dir: &Path, output: &mut impl Write) {
-    writeln!(output).unwrap();
+#[test]
+fn empty_dirs_out_of_range_is_disallowed() {
+    let dir = InspectableTempDir::new();
 
-    let mut hasher = DefaultHasher::new();
+    let result = Generator::builder()
+        .root_dir(dir.path.clone())
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1).unwrap(),
+        ))
+        .empty_dirs(1.5)
+        .build()
+        .generate(&mut String::new());
 
-    let mut entries = Vec::new();
-    let mut queue = VecDeque::from([dir.to_path_buf()]);
-    while let Some(path) = queue.pop_front() {
-        for entry in path.read_dir().unwrap() {
-            entries.push(entry.unwrap());
-        }
+    drop(result.unwrap_err());
+}
 
-        entries.sort_by_key(DirEntry::file_name);
-        for entry in &entries {
-            if entry.file_type().unwrap().is_dir() {
-                queue.push_back(entry.path());
-            } else if entry.metadata().unwrap().len() > 0 {
-                io::copy(
-                    &mut File::open(entry.path()).unwrap(),
-                    &mut (&mut hasher).write_adapter(),
-                )
-                .unwrap();
-            }
+#[rstest]
+fn resume_regenerates_missing_files(
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+) {
+    let dir = InspectableTempDir::new();
+    let mut expected = String::new();
+    let mut golden = String::new();
 
-            hasher.write(entry.file_name().to_str().unwrap().as_bytes());
-            #[cfg(not(windows))]
-            writeln!(
-                output,
-                "{}",
-                &entry.path().to_str().unwrap()[dir.as_os_str().len()..]
-            )
-            .unwrap();
-            #[cfg(windows)]
-            writeln!(
-                output,
-                "{}",
-                &entry.path().to_str().unwrap()[dir.as_os_str().len()..].replace('\\', "/")
-            )
-            .unwrap();
-        }
-        entries.clear();
-    }
+    let generator =
+        || tree_builder!(dir.path.clone(), false, bytes, files_exact).seed_version(SeedVersion::V1);
 
-    writeln!(output, "\n0x{:x}", hasher.finish()).unwrap();
-}
+    generator().build().generate(&mut String::new()).unwrap();
+    print_and_hash_dir(&dir.path, &mut expected);
 
-fn find_max_depth(dir: &Path) -> u32 {
-    let mut depth = 0;
-    for entry in dir.read_dir().unwrap() {
-        let path = entry.unwrap().path();
-        if path.is_dir() {
-            depth = max(depth, find_max_depth(&path) + 1);
-        }
-    }
-    depth
+    damage_files(&dir.path);
+    generator()
+        .resume(true)
+        .build()
+        .generate(&mut golden)
+        .unwrap();
+    print_and_hash_dir(&dir.path, &mut golden);
+
+    assert!(golden.ends_with(&expected));
+    expect_file![format!(
+        "../testdata/generator/resume_regenerates_missing_files{}{}{}.stdout",
+        if files_exact { "_exact" } else { "" },
+        if bytes.0 > 0 {
+            format!("_bytes_{}", bytes.0)
+        } else {
+            String::new()
+        },
+        if bytes.1 { "_exact" } else { "" },
+    )]
+    .assert_eq(&golden);
 }
 
-fn count_num_files(dir: &Path) -> u64 {
-    let mut num_files = 0;
-    let mut queue = VecDeque::from([dir.to_path_buf()]);
-    while let Some(path) = queue.pop_front() {
-        for entry in path.read_dir().unwrap() {
-            let entry = entry.unwrap();
-            if entry.file_type().unwrap().is_dir() {
-                queue.push_back(entry.path());
-            } else {
-                num_files += 1;
-            }
-        }
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn stopped_run_can_be_resumed(
+    #[values(false, true)] flat: bool,
+    #[values(false, true)] time_limit: bool,
+) {
+    let dir = InspectableTempDir::new();
+    let mut expected = String::new();
+    let mut resumed = String::new();
+
+    let generator = |root_dir: &Path| {
+        Generator::builder()
+            .root_dir(root_dir)
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                // Flat trees are split into tasks of 16K files
+                NonZeroU64::new(if flat { 70_000 } else { 1_000 }).unwrap(),
+            ))
+            .num_bytes(100_000)
+            .flat(flat)
+    };
+
+    let total = generator(&dir.path.join("expected"))
+        .build()
+        .run()
+        .unwrap()
+        .files;
+    print_and_hash_dir(&dir.path.join("expected"), &mut expected);
+
+    let stopped = if time_limit {
+        generator(&dir.path.join("resumed"))
+            .time_limit(Duration::ZERO)
+            .build()
+    } else {
+        // Cancel once the first tasks are done, leaving the rest unqueued
+        let token = CancellationToken::new();
+        generator(&dir.path.join("resumed"))
+            .cancellation(token.clone())
+            .observer(Arc::new(CountingObserver {
+                cancel_after: Some((if flat { 1 } else { 10 }, token)),
+                ..CountingObserver::default()
+            }))
+            .jobs(NonZeroUsize::new(1).unwrap())
+            .queue_depth(NonZeroUsize::new(3).unwrap())
+            .build()
     }
-    num_files
+    .run()
+    .unwrap_err();
+    assert!(matches!(stopped.current_context(), Error::Cancelled));
+    assert!(!dir.path.join("resumed").join(JOURNAL).exists());
+    let partial = stopped.downcast_ref::<GeneratorReport>().unwrap();
+    assert!(partial.stopped_early);
+    assert_eq!(partial.files, count_num_files(&dir.path.join("resumed")));
+    assert_lt!(partial.files, total);
+    if !time_limit {
+        assert_gt!(partial.files, 0);
+    }
+
+    generator(&dir.path.join("resumed"))
+        .resume(true)
+        .build()
+        .generate(&mut String::new())
+        .unwrap();
+    print_and_hash_dir(&dir.path.join("resumed"), &mut resumed);
+
+    assert_eq!(resumed, expected);
 }
 
-fn count_num_bytes(dir: &Path) -> u64 {
-    let mut num_bytes = 0;
-    let mut queue = VecDeque::from([dir.to_path_buf()]);
-    while let Some(path) = queue.pop_front() {
-        for entry in path.read_dir().unwrap() {
-            let entry = entry.unwrap();
-            if entry.file_type().unwrap().is_dir() {
-                queue.push_back(entry.path());
-            } else {
-                num_bytes += entry.metadata().unwrap().len();
-            }
-        }
-    }
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn resume_skips_completed_tasks(#[values(false, true)] digest: bool) {
+    let dir = InspectableTempDir::new();
+    let mut expected = String::new();
+    let mut resumed = String::new();
+
+    let generator = |root_dir: &Path, observer: &Arc<CountingObserver>| {
+        Generator::builder()
+            .root_dir(root_dir)
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(10_000).unwrap(),
+            ))
+            .num_bytes(1_000_000)
+            .digest(digest)
+            .observer(observer.clone())
+    };
+
+    let full = Arc::new(CountingObserver::default());
+    let expected_report = generator(&dir.path.join("expected"), &full)
+        .build()
+        .run()
+        .unwrap();
+    print_and_hash_dir(&dir.path.join("expected"), &mut expected);
+
+    let token = CancellationToken::new();
+    let stopped = Arc::new(CountingObserver {
+        cancel_after: Some((50, token.clone())),
+        ..CountingObserver::default()
+    });
+    let stopped = generator(&dir.path.join("resumed"), &stopped)
+        .resume(true)
+        .cancellation(token)
+        .jobs(NonZeroUsize::new(1).unwrap())
+        .queue_depth(NonZeroUsize::new(8).unwrap())
+        .build()
+        .run()
+        .unwrap_err();
+    assert!(matches!(stopped.current_context(), Error::Cancelled));
+    assert!(dir.path.join("resumed").join(JOURNAL).exists());
+
+    let remaining = Arc::new(CountingObserver::default());
+    let report = generator(&dir.path.join("resumed"), &remaining)
+        .resume(true)
+        .build()
+        .run()
+        .unwrap();
+    print_and_hash_dir(&dir.path.join("resumed"), &mut resumed);
+
+    assert_eq!(resumed, expected);
+    assert!(!dir.path.join("resumed").join(JOURNAL).exists());
+    let tasks = |observer: &CountingObserver| observer.tasks.load(Ordering::Relaxed);
+    assert!(tasks(&remaining) < tasks(&full) - 40, "{remaining:?}");
+    assert!(report.reused_files > 0);
+    assert_eq!(
+        (report.files, report.dirs, report.bytes, report.empty_files),
+        (
+            expected_report.files,
+            expected_report.dirs,
+            expected_report.bytes,
+            expected_report.empty_files
+        )
+    );
+    assert_eq!(report.digest, expected_report.digest);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn manifest_describes_every_entry(
+    #[values(ManifestFormat::JsonLines, ManifestFormat::Csv)] format: ManifestFormat,
+    #[values(0, 100_000)] bytes: u64,
+    #[values(false, true)] exact: bool,
+) {
+    let dir = InspectableTempDir::new();
+    let manifest_dir = InspectableTempDir::new();
+    let manifest = manifest_dir.path.join("manifest");
+    let mut golden = String::new();
+
+    Generator::builder()
+        .root_dir(dir.path.clone())
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1_000).unwrap(),
+        ))
+        .num_bytes(bytes)
+        .files_exact(exact)
+        .bytes_exact(exact)
+        .manifest(manifest.clone())
+        .manifest_format(format)
+        .seed_version(SeedVersion::V1)
+        .build()
+        .generate(&mut golden)
+        .unwrap();
+
+    let contents = std::fs::read_to_string(&manifest).unwrap();
+    let mut records = contents.lines();
+    if format == ManifestFormat::Csv {
+        assert_eq!(records.next(), Some("path,type,size,mode,hash"));
+    }
+
+    let (mut num_files, mut num_dirs) = (0, 0);
+    for record in records {
+        let [path, kind, size, mode, hash] = match format {
+            ManifestFormat::JsonLines => ["path", "type", "size", "mode", "hash"].map(|key| {
+                record
+                    .split_once(&format!("\"{key}\":"))
+                    .map_or("", |(_, value)| {
+                        value
+                            .trim_start_matches('"')
+                            .split(['"', ',', '}'])
+                            .next()
+                            .unwrap()
+                    })
+            }),
+            ManifestFormat::Csv => {
+                let fields = record.split(',').collect::<Vec<_>>();
+                fields.try_into().unwrap()
+            }
+        };
+
+        let path = dir.path.join(path);
+        let metadata = path.symlink_metadata().unwrap();
+        #[cfg(unix)]
+        assert_eq!(
+            u32::from_str_radix(mode, 8).unwrap(),
+            std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777,
+            "{record}"
+        );
+        if kind == "dir" {
+            assert!(metadata.is_dir(), "{record}");
+            assert_eq!((size, hash), ("", ""), "{record}");
+            num_dirs += 1;
+        } else {
+            assert_eq!(kind, "file", "{record}");
+            assert_eq!(size.parse::<u64>().unwrap(), metadata.len(), "{record}");
+            assert_eq!(hash, format!("{:016x}", fnv1a(&path)), "{record}");
+            num_files += 1;
+        }
+    }
+    assert_eq!(num_files, count_num_files(&dir.path));
+    assert_eq!(num_dirs, count_num_dirs(&dir.path));
+
+    expect_file![format!(
+        "../testdata/generator/manifest_describes_every_entry_{}{}{}.stdout",
+        match format {
+            ManifestFormat::JsonLines => "jsonl",
+            ManifestFormat::Csv => "csv",
+        },
+        if bytes > 0 {
+            format!("_bytes_{bytes}")
+        } else {
+            String::new()
+        },
+        if exact { "_exact" } else { "" },
+    )]
+    .assert_eq(&golden);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn manifest_is_written_in_scheduler_order(
+    #[values(0, 5)] max_depth: u32,
+    #[values(false, true)] flat: bool,
+) {
+    let dir = InspectableTempDir::new();
+
+    // Records of a directory with this many files are written in several chunks
+    let manifest = |jobs: usize| {
+        let manifest = dir.path.join(format!("manifest-{jobs}"));
+        Generator::builder()
+            .root_dir("root")
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(20_000).unwrap(),
+            ))
+            .files_exact(true)
+            .max_depth(max_depth)
+            .flat(flat)
+            .manifest(manifest.clone())
+            .jobs(NonZeroUsize::new(jobs).unwrap())
+            .backend(Arc::new(MemoryBackend::new()))
+            .build()
+            .run()
+            .unwrap();
+        std::fs::read_to_string(manifest).unwrap()
+    };
+
+    let serial = manifest(1);
+    assert_eq!(
+        serial
+            .lines()
+            .filter(|record| record.contains("\"file\""))
+            .count(),
+        20_000
+    );
+    assert!(serial == manifest(8));
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn dry_run_matches_real_run(
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+) {
+    let dir = InspectableTempDir::new();
+    let root_dir = dir.path.join("root");
+    let mut planned = String::new();
+    let mut created = String::new();
+
+    let generator = || tree_builder!(root_dir.clone(), flat, bytes, files_exact);
+
+    generator()
+        .dry_run(true)
+        .build()
+        .generate(&mut planned)
+        .unwrap();
+    assert!(!root_dir.exists());
+    generator().build().generate(&mut created).unwrap();
+
+    assert_eq!(planned.replace("Would create", "Created"), created);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn archive_matches_real_run(
+    #[values(
+        ArchiveFormat::Tar,
+        ArchiveFormat::Zip(ZipCompression::Stored),
+        ArchiveFormat::Zip(ZipCompression::Deflated),
+        ArchiveFormat::Cpio
+    )]
+    format: ArchiveFormat,
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+) {
+    let dir = InspectableTempDir::new();
+    let archive = dir.path.join("tree.archive");
+    let mut archived = String::new();
+    let mut created = String::new();
+
+    let generator = |root_dir: &Path| tree_builder!(root_dir, flat, bytes, files_exact);
+
+    generator(Path::new("out/root"))
+        .archive(archive.clone())
+        .archive_format(format)
+        .build()
+        .generate(&mut archived)
+        .unwrap();
+    assert!(!Path::new("out").exists());
+    generator(&dir.path.join("out/root"))
+        .build()
+        .generate(&mut created)
+        .unwrap();
+
+    assert_eq!(archived.replace("Archived", "Created"), created);
+    let contents = fs::read(&archive).unwrap();
+    assert_eq!(
+        match format {
+            ArchiveFormat::Tar => tar_entries(&contents),
+            ArchiveFormat::Zip(_) => zip_entries(&contents),
+            ArchiveFormat::Cpio => cpio_entries(&contents),
+        },
+        tree_entries(&dir.path, &dir.path.join("out/root"))
+    );
+
+    generator(Path::new("out/root"))
+        .archive(archive.clone())
+        .archive_format(format)
+        .build()
+        .run()
+        .unwrap();
+    assert_eq!(fs::read(&archive).unwrap(), contents);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn backend_matches_real_run(
+    #[values(false, true)] forwarding: bool,
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+    #[values(0, 42)] seed: u64,
+) {
+    let dir = InspectableTempDir::new();
+    let memory = Arc::new(MemoryBackend::new());
+    let backend: Arc<dyn Backend> = if forwarding {
+        Arc::new(ForwardingBackend {
+            backend: memory.clone(),
+            ..ForwardingBackend::default()
+        })
+    } else {
+        memory.clone()
+    };
+    let mut recorded = String::new();
+    let mut created = String::new();
+
+    let generator = |root_dir: &Path| tree_builder!(root_dir, flat, bytes, files_exact).seed(seed);
+
+    generator(Path::new("root"))
+        .backend(backend.clone())
+        .build()
+        .generate(&mut recorded)
+        .unwrap();
+    assert!(!Path::new("root").exists());
+    generator(&dir.path.join("root"))
+        .build()
+        .generate(&mut created)
+        .unwrap();
+
+    assert_eq!(recorded, created);
+    let entries = memory_entries(memory.entries());
+    assert_eq!(entries, tree_entries(&dir.path, &dir.path.join("root")));
+
+    let report = generator(Path::new("root"))
+        .backend(backend)
+        .resume(true)
+        .build()
+        .run()
+        .unwrap();
+    assert_eq!(report.reused_files, report.files);
+    assert_eq!(memory_entries(memory.entries()), entries);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn tree_is_independent_of_jobs(
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (1_000_000, false), (1_000_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+) {
+    let generate = |jobs: Option<usize>, queue_depth: Option<usize>| {
+        let backend = Arc::new(MemoryBackend::new());
+        let report = Generator::builder()
+            .root_dir("root")
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(20_000).unwrap(),
+            ))
+            .num_bytes(bytes.0)
+            .files_exact(files_exact)
+            .bytes_exact(bytes.1)
+            .flat(flat)
+            .maybe_jobs(jobs.map(|jobs| NonZeroUsize::new(jobs).unwrap()))
+            .maybe_queue_depth(queue_depth.map(|depth| NonZeroUsize::new(depth).unwrap()))
+            .backend(backend.clone())
+            .build()
+            .run()
+            .unwrap();
+        (
+            (report.files, report.dirs, report.bytes),
+            Arc::into_inner(backend).unwrap().into_entries(),
+        )
+    };
+
+    // A single job flushes the task queue as often as possible while many jobs
+    // never flush it
+    let (expected_stats, expected_entries) = generate(Some(1), None);
+    for (jobs, queue_depth) in [
+        (Some(2), None),
+        (Some(3), None),
+        (Some(8), None),
+        (Some(256), None),
+        (None, None),
+        (Some(1), Some(1)),
+        (Some(8), Some(2)),
+        (Some(2), Some(100_000)),
+    ] {
+        let (stats, entries) = generate(jobs, queue_depth);
+        assert_eq!(
+            stats, expected_stats,
+            "{jobs:?} jobs, {queue_depth:?} queue depth"
+        );
+        assert!(
+            entries == expected_entries,
+            "{jobs:?} jobs, {queue_depth:?} queue depth"
+        );
+    }
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn queue_depth_bounds_in_flight_tasks(
+    #[values(1, 2, 5)] queue_depth: usize,
+    #[values(false, true)] flat: bool,
+) {
+    let backend = Arc::new(ConcurrencyBackend::default());
+    Generator::builder()
+        .root_dir("root")
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            // Flat trees are split into tasks of 16K files
+            NonZeroU64::new(if flat { 100_000 } else { 1_000 }).unwrap(),
+        ))
+        .flat(flat)
+        .jobs(NonZeroUsize::new(8).unwrap())
+        .queue_depth(NonZeroUsize::new(queue_depth).unwrap())
+        .backend(backend.clone())
+        .build()
+        .run()
+        .unwrap();
+
+    // Each task creates its entries one at a time, so the number of concurrent
+    // calls is the number of running tasks
+    let max_active = backend.max_active.load(Ordering::Relaxed);
+    assert_le!(max_active, queue_depth);
+    if queue_depth > 1 {
+        assert_gt!(max_active, 1);
+    }
+}
+
+#[rstest]
+#[case::files(Some(200), None)]
+#[case::bytes(None, Some(400_000))]
+#[case::both(Some(400), Some(400_000))]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn throttle_limits_rates(
+    #[case] max_files_per_sec: Option<u64>,
+    #[case] max_bytes_per_sec: Option<u64>,
+) {
+    let generate = |throttled: bool| {
+        let backend = Arc::new(MemoryBackend::new());
+        let mut output = String::new();
+        let report = Generator::builder()
+            .root_dir("root")
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(100).unwrap(),
+            ))
+            .num_bytes(100_000)
+            .files_exact(true)
+            .bytes_exact(true)
+            .maybe_max_files_per_sec(
+                max_files_per_sec
+                    .filter(|_| throttled)
+                    .map(|rate| NonZeroU64::new(rate).unwrap()),
+            )
+            .maybe_max_bytes_per_sec(
+                max_bytes_per_sec
+                    .filter(|_| throttled)
+                    .map(|rate| NonZeroU64::new(rate).unwrap()),
+            )
+            .backend(backend.clone())
+            .build()
+            .generate(&mut output)
+            .unwrap();
+        (
+            report,
+            output,
+            Arc::into_inner(backend).unwrap().into_entries(),
+        )
+    };
+
+    let (report, output, entries) = generate(true);
+    let (unthrottled, _, expected_entries) = generate(false);
+    assert!(entries == expected_entries);
+    assert_eq!(unthrottled.rates, None);
+
+    // The first file is written immediately and each following one waits for
+    // its share of the limits, so at worst the largest file comes first
+    let largest_file = entries
+        .values()
+        .filter_map(|entry| match entry {
+            Entry::File { contents, mode: _ } => Some(contents.len()),
+            Entry::Dir => None,
+        })
+        .max()
+        .unwrap();
+    let min_secs = f64::max(
+        max_files_per_sec.map_or(0., |rate| 99. / rate as f64),
+        max_bytes_per_sec.map_or(0., |rate| (100_000 - largest_file) as f64 / rate as f64),
+    );
+    assert!(report.elapsed.as_secs_f64() >= min_secs, "{report:?}");
+
+    let rates = report.rates.unwrap();
+    assert!(output.contains(" per second."), "{output}");
+    if let Some(rate) = max_files_per_sec {
+        assert_le!(rates.files_per_sec, rate * 11 / 10);
+    }
+    if let Some(rate) = max_bytes_per_sec {
+        assert_le!(rates.bytes_per_sec, rate * 11 / 10);
+    }
+}
+
+#[test]
+fn throttled_archive_is_disallowed() {
+    let dir = InspectableTempDir::new();
+
+    let result = Generator::builder()
+        .root_dir(dir.path.join("root"))
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1).unwrap(),
+        ))
+        .archive(dir.path.join("root.tar"))
+        .max_files_per_sec(NonZeroU64::new(1).unwrap())
+        .build()
+        .generate(&mut String::new());
+
+    drop(result.unwrap_err());
+}
+
+#[test]
+fn throttled_dry_run_is_disallowed() {
+    let dir = InspectableTempDir::new();
+
+    let result = Generator::builder()
+        .root_dir(dir.path.join("root"))
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1).unwrap(),
+        ))
+        .dry_run(true)
+        .max_bytes_per_sec(NonZeroU64::new(1).unwrap())
+        .build()
+        .generate(&mut String::new());
+
+    drop(result.unwrap_err());
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn sync_policy_is_applied(
+    #[values(
+        SyncPolicy::None,
+        SyncPolicy::File,
+        SyncPolicy::Dir,
+        SyncPolicy::Filesystem
+    )]
+    policy: SyncPolicy,
+    #[values(false, true)] flat: bool,
+    #[values(false, true)] resume: bool,
+) {
+    let backend = Arc::new(ForwardingBackend::default());
+    let generator = Generator::builder()
+        .root_dir("root")
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1_000).unwrap(),
+        ))
+        .num_bytes(10_000)
+        .flat(flat)
+        .resume(resume)
+        .sync(policy)
+        .backend(backend.clone())
+        .build();
+    let mut output = String::new();
+    let report = generator.generate(&mut output).unwrap();
+    let ForwardingBackend {
+        backend,
+        files,
+        dirs,
+        all,
+    } = Arc::into_inner(backend).unwrap();
+    let (files, dirs, all) = (
+        files.into_inner().unwrap(),
+        dirs.into_inner().unwrap(),
+        all.into_inner().unwrap(),
+    );
+    let entries = Arc::into_inner(backend).unwrap().into_entries();
+
+    assert_eq!(report.sync_elapsed.is_some(), policy != SyncPolicy::None);
+    assert_eq!(output.contains(" syncing."), policy != SyncPolicy::None);
+    match policy {
+        SyncPolicy::File => {
+            assert_eq!(files.len() as u64, report.files);
+            assert!(
+                files
+                    .iter()
+                    .all(|path| matches!(entries[path], Entry::File { .. }))
+            );
+        }
+        SyncPolicy::Dir => {
+            assert!(!dirs.is_empty());
+            assert!(dirs.iter().all(|path| matches!(entries[path], Entry::Dir)));
+        }
+        SyncPolicy::Filesystem => assert_eq!(all, [Path::new("root")]),
+        SyncPolicy::None => {}
+    }
+    for (kind, synced) in [
+        (SyncPolicy::File, files.len()),
+        (SyncPolicy::Dir, dirs.len()),
+        (SyncPolicy::Filesystem, all.len()),
+    ] {
+        if kind != policy {
+            assert_eq!(synced, 0, "{kind:?}");
+        }
+    }
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn synced_tree_matches_unsynced(
+    #[values(SyncPolicy::File, SyncPolicy::Dir, SyncPolicy::Filesystem)] policy: SyncPolicy,
+    #[values(false, true)] flat: bool,
+    #[values(false, true)] throttled: bool,
+) {
+    let dir = InspectableTempDir::new();
+
+    let generate = |name: &str, policy: SyncPolicy| {
+        Generator::builder()
+            .root_dir(dir.path.join(name))
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(200).unwrap(),
+            ))
+            .num_bytes(20_000)
+            .flat(flat)
+            .sync(policy)
+            .maybe_max_files_per_sec(throttled.then(|| NonZeroU64::new(100_000).unwrap()))
+            .build()
+            .run()
+            .unwrap()
+    };
+
+    let synced = generate("synced", policy);
+    let unsynced = generate("unsynced", SyncPolicy::None);
+    assert!(synced.sync_elapsed.is_some());
+    assert_eq!(unsynced.sync_elapsed, None);
+    assert_eq!(
+        tree_entries(&dir.path.join("synced"), &dir.path.join("synced")),
+        tree_entries(&dir.path.join("unsynced"), &dir.path.join("unsynced"))
+    );
+}
+
+#[test]
+fn synced_archive_is_disallowed() {
+    let dir = InspectableTempDir::new();
+
+    let result = Generator::builder()
+        .root_dir(dir.path.join("root"))
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1).unwrap(),
+        ))
+        .archive(dir.path.join("root.tar"))
+        .sync(SyncPolicy::File)
+        .build()
+        .generate(&mut String::new());
+
+    drop(result.unwrap_err());
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn planned_entries_follow_archive_order(
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+) {
+    let dir = InspectableTempDir::new();
+    let archive = dir.path.join("tree.tar");
+
+    let generator = || tree_builder!("root", flat, bytes, files_exact);
+
+    let archived = generator().archive(archive.clone()).build().run().unwrap();
+    let mut planned = generator().build().plan().unwrap();
+    let entries = planned.by_ref().collect::<Vec<_>>();
+    let report = planned.finish().unwrap();
+    assert!(!Path::new("root").exists());
+    assert_eq!(
+        (report.files, report.dirs, report.bytes),
+        (archived.files, archived.dirs, archived.bytes)
+    );
+
+    let expected = ordered_tar_entries(&fs::read(&archive).unwrap())
+        .into_iter()
+        .skip(1) // The root directory
+        .map(|(name, data)| (name, data.map(|data| data.len() as u64)))
+        .collect::<Vec<_>>();
+    let actual = entries
+        .iter()
+        .map(
+            |PlannedEntry {
+                 path, kind, size, ..
+             }| {
+                let name = path.to_str().unwrap().replace('\\', "/");
+                match kind {
+                    EntryKind::Dir => (format!("{name}/"), None),
+                    EntryKind::File => (name, Some(*size)),
+                }
+            },
+        )
+        .collect::<Vec<_>>();
+    assert_eq!(actual, expected);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn planned_content_seeds_regenerate_files(
+    #[values(false, true)] flat: bool,
+    #[values((100_000, false), (100_000, true))] bytes: (u64, bool),
+    #[values(None, Some(b'x'))] fill_byte: Option<u8>,
+) {
+    let dir = InspectableTempDir::new();
+    let root_dir = dir.path.join("root");
+
+    let generator =
+        |root_dir| tree_builder!(root_dir, flat, bytes, false).maybe_fill_byte(fill_byte);
+
+    generator(root_dir.clone()).build().run().unwrap();
+    let planned_dir = dir.path.join("planned");
+    let mut planned = generator(planned_dir.clone()).build().plan().unwrap();
+    let mut regenerated = 0;
+    for PlannedEntry {
+        path,
+        kind,
+        size,
+        content_seed,
+    } in planned.by_ref()
+    {
+        let mut contents = Vec::new();
+        if let Some(mut seed) = content_seed {
+            seed.contents(size).read_to_end(&mut contents).unwrap();
+            regenerated += 1;
+        }
+        match kind {
+            EntryKind::Dir => assert!(contents.is_empty()),
+            EntryKind::File => assert_eq!(
+                contents,
+                fs::read(root_dir.join(path.strip_prefix(&planned_dir).unwrap())).unwrap(),
+                "{path:?}"
+            ),
+        }
+    }
+    planned.finish().unwrap();
+    assert_gt!(regenerated, 0);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn report_describes_tree(
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+) {
+    let dir = InspectableTempDir::new();
+    let root_dir = dir.path.join("root");
+
+    let report = tree_builder!(root_dir.clone(), flat, bytes, false)
+        .digest(true)
+        .build()
+        .run()
+        .unwrap();
+
+    assert_eq!(report.root_dir, root_dir);
+    assert_eq!(report.files, count_num_files(&root_dir));
+    assert_eq!(report.dirs, count_num_dirs(&root_dir));
+    assert_eq!(report.bytes, count_num_bytes(&root_dir));
+    assert_eq!(report.empty_files, count_num_empty_files(&root_dir));
+    assert_eq!(report.reused_files, 0);
+    assert_eq!(report.digest, Some(tree_digest(&root_dir)));
+    assert!(!report.dry_run);
+
+    let Phases {
+        setup,
+        generation,
+        finish,
+        ..
+    } = report.phases;
+    assert!(generation > Duration::ZERO, "{report:?}");
+    assert_le!(setup + generation + finish, report.elapsed);
+}
+
+#[derive(Debug, Default)]
+struct CountingObserver {
+    observes_files: bool,
+    tasks: AtomicU64,
+    task_files: AtomicU64,
+    task_bytes: AtomicU64,
+    files: AtomicU64,
+    file_bytes: AtomicU64,
+    /// Cancels the token once this many tasks have completed.
+    cancel_after: Option<(u64, CancellationToken)>,
+}
+
+impl Observer for CountingObserver {
+    fn task_completed(&self, task: &CompletedTask<'_>) {
+        let tasks = self.tasks.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some((cancel_after, token)) = &self.cancel_after
+            && tasks >= *cancel_after
+        {
+            token.cancel();
+        }
+        self.task_files.fetch_add(task.files, Ordering::Relaxed);
+        self.task_bytes.fetch_add(task.bytes, Ordering::Relaxed);
+    }
+
+    fn observes_files(&self) -> bool {
+        self.observes_files
+    }
+
+    fn file_created(&self, _: &Path, bytes: u64) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+        self.file_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn observer_sees_every_entry(
+    #[values(false, true)] flat: bool,
+    #[values(false, true)] observes_files: bool,
+) {
+    let dir = InspectableTempDir::new();
+    let observer = Arc::new(CountingObserver {
+        observes_files,
+        ..CountingObserver::default()
+    });
+
+    let report = Generator::builder()
+        .root_dir(dir.path.join("root"))
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1_000).unwrap(),
+        ))
+        .num_bytes(100_000)
+        .flat(flat)
+        .observer(observer.clone())
+        .build()
+        .run()
+        .unwrap();
+
+    assert!(observer.tasks.load(Ordering::Relaxed) > 0);
+    assert_eq!(observer.task_files.load(Ordering::Relaxed), report.files);
+    assert_eq!(observer.task_bytes.load(Ordering::Relaxed), report.bytes);
+    if observes_files {
+        assert_eq!(observer.files.load(Ordering::Relaxed), report.files);
+        assert_eq!(observer.file_bytes.load(Ordering::Relaxed), report.bytes);
+    } else {
+        assert_eq!(observer.files.load(Ordering::Relaxed), 0);
+    }
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn digest_describes_tree(
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+) {
+    let dir = InspectableTempDir::new();
+    let mut golden = String::new();
+
+    let generator = |root_dir: &Path, seed: u64| {
+        tree_builder!(root_dir, flat, bytes, false)
+            .digest(true)
+            .seed(seed)
+            .seed_version(SeedVersion::V1)
+    };
+    let digest = |output: &str| output.split("digest is ").nth(1).unwrap().to_string();
+
+    generator(&dir.path.join("a"), 0)
+        .build()
+        .generate(&mut golden)
+        .unwrap();
+    let expected = digest(&golden);
+    assert_eq!(
+        expected,
+        format!("{:016x}.\n", tree_digest(&dir.path.join("a")))
+    );
+
+    for (root_dir, seed, dry_run) in [("b", 0, false), ("c", 0, true), ("d", 1, false)] {
+        let mut output = String::new();
+        generator(&dir.path.join(root_dir), seed)
+            .dry_run(dry_run)
+            .build()
+            .generate(&mut output)
+            .unwrap();
+        assert_eq!(digest(&output) == expected, seed == 0, "{output}");
+    }
+
+    expect_file![format!(
+        "../testdata/generator/digest_describes_tree{}{}{}.stdout",
+        if flat { "_flat" } else { "" },
+        if bytes.0 > 0 {
+            format!("_bytes_{}", bytes.0)
+        } else {
+            String::new()
+        },
+        if bytes.1 { "_exact" } else { "" },
+    )]
+    .assert_eq(&golden);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn seed_version_is_respected(#[values(false, true)] flat: bool) {
+    let dir = InspectableTempDir::new();
+    let mut golden = String::new();
+
+    let generate = |root_dir: &str, seed_version: SeedVersion, output: &mut String| {
+        Generator::builder()
+            .root_dir(dir.path.join(root_dir))
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(1_000).unwrap(),
+            ))
+            .num_bytes(100_000)
+            .flat(flat)
+            .digest(true)
+            .seed_version(seed_version)
+            .build()
+            .generate(output)
+            .unwrap();
+    };
+
+    generate("a", SeedVersion::default(), &mut golden);
+    let mut v2 = String::new();
+    generate("b", SeedVersion::V2, &mut v2);
+    let mut v1 = String::new();
+    generate("c", SeedVersion::V1, &mut v1);
+
+    assert_eq!(golden, v2);
+    // Flat trees use the seed as is
+    assert_eq!(golden == v1, flat, "{v1}");
+
+    expect_file![format!(
+        "../testdata/generator/seed_version_is_respected{}.stdout",
+        if flat { "_flat" } else { "" },
+    )]
+    .assert_eq(&golden);
+}
+
+#[rstest]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn verify_accepts_generated_tree(
+    #[values(false, true)] flat: bool,
+    #[values((0, false), (100_000, false), (100_000, true))] bytes: (u64, bool),
+    #[values(false, true)] files_exact: bool,
+) {
+    let dir = InspectableTempDir::new();
+
+    let generator = || tree_builder!(dir.path.clone(), flat, bytes, files_exact).build();
+
+    generator().generate(&mut String::new()).unwrap();
+    generator().verify(&mut String::new()).unwrap();
+}
+
+#[test]
+fn verify_accepts_dirs_only_tree() {
+    let dir = InspectableTempDir::new();
+
+    let generator = || {
+        Generator::builder()
+            .root_dir(dir.path.clone())
+            .num_files_with_ratio(NumFilesWithRatio::dirs_only(NonZeroU64::new(100).unwrap()))
+            .empty_dirs(0.5)
+            .build()
+    };
+
+    generator().generate(&mut String::new()).unwrap();
+    generator().verify(&mut String::new()).unwrap();
+}
+
+#[test]
+fn verify_reports_extra_entries_in_empty_dirs() {
+    let dir = InspectableTempDir::new();
+
+    let generator = || {
+        Generator::builder()
+            .root_dir(dir.path.clone())
+            .num_files_with_ratio(NumFilesWithRatio::dirs_only(NonZeroU64::new(100).unwrap()))
+            .empty_dirs(0.5)
+            .build()
+    };
+
+    generator().generate(&mut String::new()).unwrap();
+    let mut queue = VecDeque::from([dir.path.clone()]);
+    let empty_dir = loop {
+        let path = queue.pop_front().unwrap();
+        let mut entries = path.read_dir().unwrap().peekable();
+        if entries.peek().is_none() {
+            break path;
+        }
+        queue.extend(entries.map(|entry| entry.unwrap().path()));
+    };
+    File::create(empty_dir.join("0")).unwrap();
+
+    let result = generator().verify(&mut String::new());
+
+    assert!(matches!(
+        result.unwrap_err().current_context(),
+        Error::VerificationFailed
+    ));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn verify_reports_discrepancies() {
+    let dir = InspectableTempDir::new();
+    let mut golden = String::new();
+
+    let generator = || {
+        Generator::builder()
+            .root_dir(dir.path.clone())
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(100).unwrap(),
+            ))
+            .num_bytes(10_000)
+            .seed_version(SeedVersion::V1)
+            .build()
+    };
+
+    generator().generate(&mut String::new()).unwrap();
+    damage_files(&dir.path);
+    File::create(dir.path.join("extra")).unwrap();
+    create_dir(dir.path.join("extra.dir")).unwrap();
+    File::create(dir.path.join("0.dir/01")).unwrap();
+    create_dir(dir.path.join("0.dir/0.dir/extra.dir")).unwrap();
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(dir.path.join("0.dir/0.dir/0"))
+        .unwrap();
+    io::Write::write_all(&mut file, b"x").unwrap();
+    drop(file);
+    remove_dir_all(dir.path.join("0.dir/0.dir/0.dir")).unwrap();
+
+    let result = generator().verify(&mut golden);
+
+    writeln!(golden, "{:?}", result.unwrap_err().current_context()).unwrap();
+    expect_file!["../testdata/generator/verify_reports_discrepancies.stdout"].assert_eq(&golden);
+}
+
+#[rstest]
+#[case("modify", Churn::builder().modify(0.5).build())]
+#[case("append", Churn::builder().append(0.5).build())]
+#[case("truncate", Churn::builder().truncate(0.5).build())]
+#[case("delete", Churn::builder().delete(0.5).build())]
+#[case("rename", Churn::builder().rename(0.5).build())]
+#[case("move", Churn::builder().relocate(0.5).build())]
+#[case("add", Churn::builder().add(0.5).build())]
+#[case(
+    "all",
+    Churn::builder()
+    .modify(0.1)
+    .append(0.1)
+    .truncate(0.1)
+    .delete(0.1)
+    .rename(0.1)
+    .relocate(0.1)
+    .add(0.1)
+    .build()
+)]
+#[cfg_attr(miri, ignore)] // Miri is way too slow
+fn mutate_applies_churn(#[case] name: &str, #[case] churn: Churn) {
+    let dirs = [InspectableTempDir::new(), InspectableTempDir::new()];
+    let mut goldens = [String::new(), String::new()];
+
+    for (dir, golden) in dirs.iter().zip(&mut goldens) {
+        Generator::builder()
+            .root_dir(dir.path.clone())
+            .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                NonZeroU64::new(1_000).unwrap(),
+            ))
+            .num_bytes(100_000)
+            .seed_version(SeedVersion::V1)
+            .build()
+            .generate(&mut String::new())
+            .unwrap();
+        Mutator::builder()
+            .root_dir(dir.path.clone())
+            .churn(churn)
+            .seed(42)
+            .build()
+            .mutate(golden)
+            .unwrap();
+        print_and_hash_dir(&dir.path, golden);
+    }
+
+    let [golden, reproduced] = goldens;
+    assert_eq!(golden, reproduced);
+    expect_file![format!(
+        "../testdata/generator/mutate_applies_churn_{name}.stdout"
+    )]
+    .assert_eq(&golden);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)] // Miri is way too slow
+fn mutate_modify_preserves_sizes() {
+    let dir = InspectableTempDir::new();
+
+    // Few bytes across many files leaves plenty of them empty
+    Generator::builder()
+        .root_dir(dir.path.clone())
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1_000).unwrap(),
+        ))
+        .num_bytes(1_000)
+        .build()
+        .generate(&mut String::new())
+        .unwrap();
+    let sizes = |entries: BTreeMap<String, Option<Vec<u8>>>| {
+        entries
+            .into_iter()
+            .map(|(name, contents)| (name, contents.map(|contents| contents.len())))
+            .collect::<Vec<_>>()
+    };
+    let before = tree_entries(&dir.path, &dir.path);
+    assert!(before.values().flatten().any(Vec::is_empty));
+
+    Mutator::builder()
+        .root_dir(dir.path.clone())
+        .churn(Churn::builder().modify(1.).build())
+        .build()
+        .mutate(&mut String::new())
+        .unwrap();
+    let after = tree_entries(&dir.path, &dir.path);
+
+    assert_ne!(before, after);
+    assert_eq!(sizes(before), sizes(after));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)] // Miri is way too slow
+fn series_derives_snapshots() {
+    let dir = InspectableTempDir::new();
+    let mut golden = String::new();
+
+    Series::builder()
+        .generator(
+            Generator::builder()
+                .root_dir(dir.path.clone())
+                .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+                    NonZeroU64::new(100).unwrap(),
+                ))
+                .num_bytes(10_000)
+                .seed_version(SeedVersion::V1)
+                .build(),
+        )
+        .snapshots(NonZeroU64::new(3).unwrap())
+        .churn(
+            Churn::builder()
+                .modify(0.1)
+                .delete(0.1)
+                .relocate(0.1)
+                .add(0.1)
+                .build(),
+        )
+        .build()
+        .generate(&mut golden)
+        .unwrap();
+    print_and_hash_dir(&dir.path, &mut golden);
+
+    expect_file!["../testdata/generator/series_derives_snapshots.stdout"].assert_eq(&golden);
+}
+
+#[rstest]
+#[case::dry_run(|root_dir| tree_builder!(root_dir, false, (0, false), false).dry_run(true).build())]
+#[case::archive(|root_dir: PathBuf| {
+    tree_builder!(root_dir.clone(), false, (0, false), false)
+        .archive(root_dir.with_extension("tar"))
+        .build()
+})]
+#[case::resume(|root_dir| tree_builder!(root_dir, false, (0, false), false).resume(true).build())]
+#[case::allow_non_empty(|root_dir| {
+    tree_builder!(root_dir, false, (0, false), false)
+        .allow_non_empty(true)
+        .build()
+})]
+#[case::manifest(|root_dir: PathBuf| {
+    tree_builder!(root_dir.clone(), false, (0, false), false)
+        .manifest(root_dir.with_extension("jsonl"))
+        .build()
+})]
+fn series_of_single_tree_is_disallowed(#[case] generator: fn(PathBuf) -> Generator) {
+    let dir = InspectableTempDir::new();
+
+    let result = Series::builder()
+        .generator(generator(dir.path.join("snapshots")))
+        .snapshots(NonZeroU64::new(2).unwrap())
+        .churn(Churn::builder().build())
+        .build()
+        .generate(&mut String::new());
+
+    drop(result.unwrap_err());
+    assert!(!dir.path.join("snapshots").exists());
+}
+
+#[test]
+fn mutate_excessive_churn_is_disallowed() {
+    let dir = InspectableTempDir::new();
+
+    let result = Mutator::builder()
+        .root_dir(dir.path.clone())
+        .churn(Churn::builder().delete(0.6).rename(0.6).build())
+        .build()
+        .mutate(&mut String::new());
+
+    drop(result.unwrap_err());
+}
+
+#[rstest]
+#[case(0)]
+#[case(42)]
+#[case(69)]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn fill_byte_is_respected(#[case] fill_byte: u8) {
+    let dir = InspectableTempDir::new();
+    let mut golden = String::new();
+
+    Generator::builder()
+        .root_dir(dir.path.clone())
+        .num_files_with_ratio(NumFilesWithRatio::from_num_files(
+            NonZeroU64::new(1_000).unwrap(),
+        ))
+        .num_bytes(100_000)
+        .fill_byte(fill_byte)
+        .seed_version(SeedVersion::V1)
+        .build()
+        .generate(&mut golden)
+        .unwrap();
+
+    let assert_fill_byte = || {
+        let mut queue = VecDeque::from([dir.path.clone()]);
+        while let Some(path) = queue.pop_front() {
+            for entry in path.read_dir().unwrap() {
+                let entry = entry.unwrap();
+                if entry.file_type().unwrap().is_dir() {
+                    queue.push_back(entry.path());
+                } else {
+                    for byte in BufReader::new(File::open(entry.path()).unwrap()).bytes() {
+                        assert_eq!(fill_byte, byte.unwrap());
+                    }
+                }
+            }
+        }
+    };
+
+    assert_fill_byte();
+    print_and_hash_dir(&dir.path, &mut golden);
+
+    expect_file![format!(
+        "../testdata/generator/fill_byte_is_respected_{fill_byte}.stdout"
+    )]
+    .assert_eq(&golden);
+}
+
+#[test]
+#[cfg_attr(miri, ignore)] // Miri is way too slow unfortunately
+fn fuzz_test() {
+    let dir = InspectableTempDir::new();
+
+    let mut rng = rand::rng();
+    let num_files = rng.random_range(1..25_000);
+    let num_bytes = if rng.random() {
+        rng.random_range(0..100_000)
+    } else {
+        0
+    };
+    let max_depth = rng.random_range(0..100);
+    let ratio = rng.random_range(1..num_files);
+    let files_exact = rng.random();
+    let bytes_exact = rng.random();
+
+    let g = Generator::builder()
+        .root_dir(dir.path.clone())
+        .num_files_with_ratio(
+            NumFilesWithRatio::new(
+                NonZeroU64::new(num_files).unwrap(),
+                NonZeroU64::new(ratio).unwrap(),
+            )
+            .unwrap(),
+        )
+        .num_bytes(num_bytes)
+        .max_depth(max_depth)
+        .files_exact(files_exact)
+        .bytes_exact(bytes_exact)
+        .build();
+    println!("Params: {g:?}");
+    g.generate(&mut stdout().write_adapter()).unwrap();
+
+    assert_le!(find_max_depth(&dir.path), max_depth);
+    if files_exact {
+        assert_eq!(count_num_files(&dir.path), num_files);
+    }
+    if bytes_exact {
+        assert_eq!(count_num_bytes(&dir.path), num_bytes);
+    }
+}
+
+/// Recursively hashes the file and directory names in dir
+fn print_and_hash_dir(dir: &Path, output: &mut impl Write) {
+    writeln!(output).unwrap();
+
+    let mut hasher = DefaultHasher::new();
+
+    let mut entries = Vec::new();
+    let mut queue = VecDeque::from([dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            entries.push(entry.unwrap());
+        }
+
+        entries.sort_by_key(DirEntry::file_name);
+        for entry in &entries {
+            if entry.file_type().unwrap().is_dir() {
+                queue.push_back(entry.path());
+            } else if entry.metadata().unwrap().len() > 0 {
+                io::copy(
+                    &mut File::open(entry.path()).unwrap(),
+                    &mut (&mut hasher).write_adapter(),
+                )
+                .unwrap();
+            }
+
+            hasher.write(entry.file_name().to_str().unwrap().as_bytes());
+            #[cfg(not(windows))]
+            writeln!(
+                output,
+                "{}",
+                &entry.path().to_str().unwrap()[dir.as_os_str().len()..]
+            )
+            .unwrap();
+            #[cfg(windows)]
+            writeln!(
+                output,
+                "{}",
+                &entry.path().to_str().unwrap()[dir.as_os_str().len()..].replace('\\', "/")
+            )
+            .unwrap();
+        }
+        entries.clear();
+    }
+
+    writeln!(output, "\n0x{:x}", hasher.finish()).unwrap();
+}
+
+fn find_max_depth(dir: &Path) -> u32 {
+    let mut depth = 0;
+    for entry in dir.read_dir().unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            depth = max(depth, find_max_depth(&path) + 1);
+        }
+    }
+    depth
+}
+
+/// Counts the generated files, ignoring the journal of an incomplete tree.
+fn count_num_files(dir: &Path) -> u64 {
+    let mut num_files = 0;
+    let mut queue = VecDeque::from([dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                queue.push_back(entry.path());
+            } else if entry.file_name() != JOURNAL {
+                num_files += 1;
+            }
+        }
+    }
+    num_files
+}
+
+fn fnv1a(file: &Path) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in BufReader::new(File::open(file).unwrap()).bytes() {
+        hash ^= u64::from(byte.unwrap());
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// An independent implementation of the generator's tree digest.
+fn tree_digest(root_dir: &Path) -> u64 {
+    fn update(hash: u64, bytes: &[u8]) -> u64 {
+        bytes.iter().fold(hash, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    let mut digest = 0u64;
+    let mut queue = VecDeque::from([root_dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            let entry = entry.unwrap();
+            let relative = entry.path().strip_prefix(root_dir).unwrap().to_path_buf();
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_str().unwrap())
+                .collect::<Vec<_>>()
+                .join("/");
+            let hash = update(0xcbf2_9ce4_8422_2325, relative.as_bytes());
+
+            let hash = if entry.file_type().unwrap().is_dir() {
+                queue.push_back(entry.path());
+                update(hash, b"/")
+            } else {
+                let content = fnv1a(&entry.path());
+                update(update(hash, b"\0"), &content.to_le_bytes())
+            };
+            digest = digest.wrapping_add(hash);
+        }
+    }
+    digest
+}
+
+fn count_num_dirs(dir: &Path) -> u64 {
+    let mut num_dirs = 0;
+    let mut queue = VecDeque::from([dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                num_dirs += 1;
+                queue.push_back(entry.path());
+            }
+        }
+    }
+    num_dirs
+}
+
+fn count_num_empty_dirs(dir: &Path) -> u64 {
+    let mut num_empty_dirs = 0;
+    let mut queue = VecDeque::from([dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        let mut is_empty = true;
+        for entry in path.read_dir().unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                queue.push_back(entry.path());
+            }
+            is_empty = false;
+        }
+        if is_empty {
+            num_empty_dirs += 1;
+        }
+    }
+    num_empty_dirs
+}
+
+fn count_num_empty_files(dir: &Path) -> u64 {
+    let mut num_empty_files = 0;
+    let mut queue = VecDeque::from([dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                queue.push_back(entry.path());
+            } else if entry.metadata().unwrap().len() == 0 {
+                num_empty_files += 1;
+            }
+        }
+    }
+    num_empty_files
+}
+
+fn count_num_bytes(dir: &Path) -> u64 {
+    let mut num_bytes = 0;
+    let mut queue = VecDeque::from([dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                queue.push_back(entry.path());
+            } else {
+                num_bytes += entry.metadata().unwrap().len();
+            }
+        }
+    }
     num_bytes
 }
+
+/// Parses a tar stream into its entries' names and file contents.
+fn tar_entries(archive: &[u8]) -> BTreeMap<String, Option<Vec<u8>>> {
+    let entries = ordered_tar_entries(archive);
+    let len = entries.len();
+    let entries = entries.into_iter().collect::<BTreeMap<_, _>>();
+    assert_eq!(entries.len(), len);
+    entries
+}
+
+fn ordered_tar_entries(archive: &[u8]) -> Vec<(String, Option<Vec<u8>>)> {
+    tar::Archive::new(archive)
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let name = String::from_utf8(entry.path_bytes().into_owned()).unwrap();
+            let kind = entry.header().entry_type();
+            if kind.is_dir() {
+                assert!(name.ends_with('/'));
+                (name, None)
+            } else {
+                assert!(kind.is_file());
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                (name, Some(data))
+            }
+        })
+        .collect()
+}
+
+/// Reads a zip file's entries, checking their contents against their CRCs.
+fn zip_entries(archive: &[u8]) -> BTreeMap<String, Option<Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(archive)).unwrap();
+    let mut entries = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).unwrap();
+        let value = if entry.is_dir() {
+            None
+        } else {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            Some(data)
+        };
+        assert!(entries.insert(entry.name().to_string(), value).is_none());
+    }
+    entries
+}
+
+/// Reads a newc cpio stream, checking that every inode is unique.
+fn cpio_entries(mut archive: &[u8]) -> BTreeMap<String, Option<Vec<u8>>> {
+    let mut entries = BTreeMap::new();
+    let mut inodes = HashSet::new();
+    loop {
+        let mut reader = cpio::NewcReader::new(archive).unwrap();
+        let entry = reader.entry();
+        if entry.is_trailer() {
+            break;
+        }
+        assert!(inodes.insert(entry.ino()));
+
+        let name = entry.name().to_string();
+        if entry.mode() & 0o170_000 == 0o040_000 {
+            assert!(entries.insert(format!("{name}/"), None).is_none());
+        } else {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            assert!(entries.insert(name, Some(data)).is_none());
+        }
+        archive = reader.finish().unwrap();
+    }
+    entries
+}
+
+/// Collects the tree's entries named as they would be in an archive,
+/// including the directories between `parent` and the root.
+fn tree_entries(parent: &Path, root_dir: &Path) -> BTreeMap<String, Option<Vec<u8>>> {
+    let name = |path: &Path| {
+        path.strip_prefix(parent)
+            .unwrap()
+            .components()
+            .map(|c| c.as_os_str().to_str().unwrap())
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+
+    let mut entries = root_dir
+        .ancestors()
+        .take_while(|dir| *dir != parent)
+        .map(|dir| (format!("{}/", name(dir)), None))
+        .collect::<BTreeMap<_, _>>();
+    let mut queue = VecDeque::from([root_dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                entries.insert(format!("{}/", name(&path)), None);
+                queue.push_back(path);
+            } else {
+                entries.insert(name(&path), Some(fs::read(&path).unwrap()));
+            }
+        }
+    }
+    entries
+}
+
+/// Names the entries of a [`MemoryBackend`] in the same form as
+/// [`tree_entries`].
+fn memory_entries(entries: HashMap<PathBuf, Entry>) -> BTreeMap<String, Option<Vec<u8>>> {
+    entries
+        .into_iter()
+        .map(|(path, entry)| {
+            let name = path
+                .components()
+                .map(|c| c.as_os_str().to_str().unwrap())
+                .collect::<Vec<_>>()
+                .join("/");
+            match entry {
+                Entry::Dir => (format!("{name}/"), None),
+                Entry::File { contents, mode: _ } => (name, Some(contents)),
+            }
+        })
+        .collect()
+}
+
+/// Forwards to a [`MemoryBackend`] as a backend implemented outside the crate
+/// would, recording the paths it was asked to sync.
+#[derive(Debug, Default)]
+struct ForwardingBackend {
+    backend: Arc<MemoryBackend>,
+    files: Mutex<Vec<PathBuf>>,
+    dirs: Mutex<Vec<PathBuf>>,
+    all: Mutex<Vec<PathBuf>>,
+}
+
+impl Backend for ForwardingBackend {
+    fn create_dir(&self, path: &Path, metadata: &EntryMetadata) -> io::Result<()> {
+        self.backend.create_dir(path, metadata)
+    }
+
+    fn create_file(
+        &self,
+        path: &Path,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+    ) -> io::Result<()> {
+        self.backend.create_file(path, metadata, contents)
+    }
+
+    fn file_metadata(&self, path: &Path) -> Option<EntryMetadata> {
+        self.backend.file_metadata(path)
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        self.files.lock().unwrap().push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        self.dirs.lock().unwrap().push(path.to_path_buf());
+        Ok(())
+    }
+
+    fn sync_all(&self, root_dir: &Path) -> io::Result<()> {
+        self.all.lock().unwrap().push(root_dir.to_path_buf());
+        Ok(())
+    }
+}
+
+/// Records the most calls a [`MemoryBackend`] was ever handling at once.
+#[derive(Debug, Default)]
+struct ConcurrencyBackend {
+    backend: MemoryBackend,
+    active: AtomicUsize,
+    max_active: AtomicUsize,
+}
+
+impl ConcurrencyBackend {
+    fn track<T>(&self, f: impl FnOnce() -> T) -> T {
+        let active = self.active.fetch_add(1, Ordering::Relaxed) + 1;
+        self.max_active.fetch_max(active, Ordering::Relaxed);
+        let result = f();
+        self.active.fetch_sub(1, Ordering::Relaxed);
+        result
+    }
+}
+
+impl Backend for ConcurrencyBackend {
+    fn create_dir(&self, path: &Path, metadata: &EntryMetadata) -> io::Result<()> {
+        self.track(|| {
+            // Give other tasks a chance to overlap with this one
+            thread::sleep(Duration::from_micros(100));
+            self.backend.create_dir(path, metadata)
+        })
+    }
+
+    fn create_file(
+        &self,
+        path: &Path,
+        metadata: &EntryMetadata,
+        contents: &mut FileContents<'_>,
+    ) -> io::Result<()> {
+        self.track(|| self.backend.create_file(path, metadata, contents))
+    }
+
+    fn file_metadata(&self, path: &Path) -> Option<EntryMetadata> {
+        self.backend.file_metadata(path)
+    }
+}
+
+/// Deletes every third file and truncates every other third of the files.
+fn damage_files(dir: &Path) {
+    let mut files = Vec::new();
+    let mut queue = VecDeque::from([dir.to_path_buf()]);
+    while let Some(path) = queue.pop_front() {
+        for entry in path.read_dir().unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                queue.push_back(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+
+    for (i, file) in files.into_iter().enumerate() {
+        match i % 3 {
+            0 => remove_file(file).unwrap(),
+            1 => {
+                let file = OpenOptions::new().write(true).open(file).unwrap();
+                let len = file.metadata().unwrap().len();
+                file.set_len(len / 2).unwrap();
+            }
+            _ => {}
+        }
+    }
+}